@@ -1,182 +1,867 @@
-//! Placeholder for SVM PDA-based program
-// TODO: Implement PDA-based wallet and program-initiated transaction logic 
+//! NexusDeFi SVM Wallet (native)
+//!
+//! Lightweight variant of `nexus_wallet` written directly against
+//! `solana-program`, for integrations where compute units matter more than
+//! Anchor's account validation. It provides the same core instructions:
+//! - Wallet initialization at a PDA derived from the owner and a seed
+//! - Call execution signed by the wallet PDA, authorized by the owner either
+//!   as a transaction signer or through an ed25519 signature
+//! - User operation validation against the owner's ed25519 signature
+//! - Guardians with majority-approved recovery and emergency freeze
+//!
+//! Wallet state is a fixed-size Borsh layout, so every instruction decodes a
+//! constant `WalletAccount::LEN` bytes and no account is ever resized.
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
-    entrypoint,
+    account_info::AccountInfo,
+    ed25519_program,
     entrypoint::ProgramResult,
+    hash::hashv,
+    instruction::{AccountMeta, Instruction},
     msg,
     program::invoke_signed,
+    program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
-    sysvar::Sysvar,
-    program_error::ProgramError,
-    instruction::{AccountMeta, Instruction},
-    signature::Signature,
-    hash::hashv,
+    sysvar::{instructions::load_instruction_at_checked, Sysvar},
 };
-use borsh::{BorshDeserialize, BorshSerialize};
+use thiserror::Error;
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+
+/// Guardian slots per wallet
+pub const MAX_GUARDIANS: usize = 8;
+
+/// Domain separators for messages the owner signs with ed25519
+pub const EXECUTE_DOMAIN: &[u8] = b"nexus_svm_execute";
+pub const USER_OP_DOMAIN: &[u8] = b"nexus_svm_user_op";
 
-// Define the program's instruction enum
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub enum WalletInstruction {
-    /// Initializes a new PDA-based wallet.
+    /// Initializes a new PDA-based wallet at `[owner, seed]`.
     /// Accounts:
-    /// 0. `[signer]` Payer for the new wallet account.
+    /// 0. `[signer, writable]` Payer for the new wallet account.
     /// 1. `[writable]` New wallet account (PDA).
     /// 2. `[]` System program.
     InitializeWallet { owner: Pubkey, seed: u64 },
-    /// Executes a transaction from the wallet.
+    /// Runs `calls` with the wallet PDA as signer.
+    /// Accounts:
+    /// 0. `[writable]` Wallet account (PDA).
+    /// 1. `[signer]` Owner, or `[]` the instructions sysvar when the owner
+    ///    authorized this instruction through an ed25519 program
+    ///    instruction over `execute_message`.
+    ///
+    /// Remaining accounts are every program and account the calls reference.
+    ExecuteTransaction { nonce: u64, calls: Vec<WalletCall> },
+    /// Checks that the owner signed a user operation for the wallet's current
+    /// nonce. Read-only: the nonce, shared with `ExecuteTransaction`, is only
+    /// consumed when the operation executes.
+    /// Accounts:
+    /// 0. `[]` Wallet account (PDA).
+    /// 1. `[]` Instructions sysvar, holding an ed25519 program instruction
+    ///    by the owner over `user_op_message`.
+    ValidateUserOp { nonce: u64, user_op_hash: [u8; 32] },
     /// Accounts:
-    /// 0. `[signer]` Wallet account (PDA).
-    /// 1. `[]` Target program to call.
-    /// Remaining accounts are for the target instruction.
-    ExecuteTransaction { instruction_data: Vec<u8>, account_metas: Vec<AccountMeta> },
-    /// Validates a user operation.
+    /// 0. `[writable]` Wallet account (PDA).
+    /// 1. `[signer]` Owner.
+    AddGuardian { guardian: Pubkey },
     /// Accounts:
-    /// 0. `[signer]` Wallet account (PDA).
-    ValidateUserOp { user_op_hash: [u8; 32], signature: [u8; 64] },
+    /// 0. `[writable]` Wallet account (PDA).
+    /// 1. `[signer]` Owner.
+    RemoveGuardian { guardian: Pubkey },
+    /// Starts recovery with the initiating guardian's approval. As in
+    /// `nexus_wallet`, recovery only completes through `ApproveRecovery`.
+    /// Accounts:
+    /// 0. `[writable]` Wallet account (PDA).
+    /// 1. `[signer]` Guardian.
+    InitiateRecovery { new_owner: Pubkey },
+    /// Completes recovery once a majority of guardians has approved.
+    /// Accounts:
+    /// 0. `[writable]` Wallet account (PDA).
+    /// 1. `[signer]` Guardian.
+    ApproveRecovery,
+    /// Accounts:
+    /// 0. `[writable]` Wallet account (PDA).
+    /// 1. `[signer]` Guardian.
+    FreezeWallet,
+    /// Accounts:
+    /// 0. `[writable]` Wallet account (PDA).
+    /// 1. `[signer]` Owner.
+    UnfreezeWallet,
+}
+
+/// A single call made by the wallet
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct WalletCall {
+    /// Program to invoke, or the recipient when `data` is empty
+    pub target: Pubkey,
+    pub accounts: Vec<CallAccount>,
+    pub data: Vec<u8>,
+    /// Lamports sent from the wallet to `target` before the call
+    pub value: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct CallAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
 }
 
-// Define the wallet account state
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct WalletAccount {
     pub owner: Pubkey,
-    pub initialized: bool,
+    /// Owner at creation; with `seed` it derives the wallet PDA
+    pub initial_owner: Pubkey,
+    pub seed: u64,
     pub nonce: u64,
-    // TODO: Add more fields like guardians, etc.
+    pub guardians: [Pubkey; MAX_GUARDIANS],
+    pub recovery_new_owner: Pubkey,
+    /// Bitmap over guardian slots
+    pub recovery_approvals: u8,
+    pub recovery_active: bool,
+    pub guardian_count: u8,
+    pub is_frozen: bool,
+    pub initialized: bool,
+    pub bump: u8,
+}
+
+impl WalletAccount {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 32 * MAX_GUARDIANS + 32 + 1 + 1 + 1 + 1 + 1 + 1;
+
+    fn guardian_index(&self, guardian: &Pubkey) -> Option<usize> {
+        self.guardians[..self.guardian_count as usize]
+            .iter()
+            .position(|g| g == guardian)
+    }
+
+    /// Majority of the current guardian set
+    fn required_approvals(&self) -> u32 {
+        (self.guardian_count as u32 / 2) + 1
+    }
 }
 
-// Declare the program entrypoint
-entrypoint!(process_instruction);
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WalletError {
+    #[error("Invalid nonce")]
+    InvalidNonce,
+    #[error("Missing or invalid owner authorization")]
+    InvalidSignature,
+    #[error("Wallet is frozen")]
+    WalletFrozen,
+    #[error("Too many guardians")]
+    TooManyGuardians,
+    #[error("Guardian already exists")]
+    GuardianAlreadyExists,
+    #[error("Guardian not found")]
+    GuardianNotFound,
+    #[error("Unauthorized guardian")]
+    UnauthorizedGuardian,
+    #[error("Recovery already in progress")]
+    RecoveryInProgress,
+    #[error("No recovery in progress")]
+    NoRecoveryInProgress,
+    #[error("Guardian already approved")]
+    AlreadyApproved,
+    #[error("Account referenced by a call was not passed")]
+    MissingCallAccount,
+    #[error("Insufficient funds")]
+    InsufficientFunds,
+}
+
+impl From<WalletError> for ProgramError {
+    fn from(e: WalletError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
 
-// Program entrypoint logic
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    msg!("SVM Program Entrypoint");
-
     let instruction = WalletInstruction::try_from_slice(instruction_data)?;
 
     match instruction {
         WalletInstruction::InitializeWallet { owner, seed } => {
             msg!("Instruction: InitializeWallet");
-            let accounts_iter = &mut accounts.iter();
+            initialize_wallet(program_id, accounts, owner, seed)
+        }
+        WalletInstruction::ExecuteTransaction { nonce, calls } => {
+            msg!("Instruction: ExecuteTransaction");
+            execute_transaction(program_id, accounts, nonce, &calls, instruction_data)
+        }
+        WalletInstruction::ValidateUserOp { nonce, user_op_hash } => {
+            msg!("Instruction: ValidateUserOp");
+            validate_user_op(program_id, accounts, nonce, &user_op_hash)
+        }
+        WalletInstruction::AddGuardian { guardian } => {
+            msg!("Instruction: AddGuardian");
+            let (wallet_info, mut wallet) = load_with_owner(program_id, accounts)?;
 
-            let payer = next_account_info(accounts_iter)?;
-            let wallet_account = next_account_info(accounts_iter)?;
-            let system_program = next_account_info(accounts_iter)?;
+            if wallet.guardian_count as usize >= MAX_GUARDIANS {
+                return Err(WalletError::TooManyGuardians.into());
+            }
+            if wallet.guardian_index(&guardian).is_some() {
+                return Err(WalletError::GuardianAlreadyExists.into());
+            }
 
-            // Derive the PDA for the wallet account
-            let (pda, bump_seed) = Pubkey::find_program_address(
-                &[owner.as_ref(), &seed.to_le_bytes()],
-                program_id,
-            );
+            let slot = wallet.guardian_count as usize;
+            wallet.guardians[slot] = guardian;
+            wallet.guardian_count += 1;
+            store_wallet(&wallet, wallet_info)
+        }
+        WalletInstruction::RemoveGuardian { guardian } => {
+            msg!("Instruction: RemoveGuardian");
+            let (wallet_info, mut wallet) = load_with_owner(program_id, accounts)?;
 
-            // Verify that the provided wallet_account is indeed the PDA
-            if pda != *wallet_account.key {
-                return Err(ProgramError::InvalidSeeds);
-            }
+            let index = wallet.guardian_index(&guardian)
+                .ok_or(WalletError::GuardianNotFound)?;
+            let last = wallet.guardian_count as usize - 1;
 
-            // Calculate rent exemption
-            let account_len = WalletAccount::LEN;
-            let rent_exemption_lamports = Rent::get()?.minimum_balance(account_len);
-
-            // Create the account via invoke_signed as PDA cannot sign directly
-            invoke_signed(
-                &system_instruction::create_account(
-                    payer.pubkey(),
-                    wallet_account.pubkey(),
-                    rent_exemption_lamports,
-                    account_len as u64,
-                    program_id,
-                ),
-                &[
-                    payer.clone(),
-                    wallet_account.clone(),
-                    system_program.clone(),
-                ],
-                &[&[owner.as_ref(), &seed.to_le_bytes(), &[bump_seed]]],
-            )?;
-
-            let mut wallet_data = WalletAccount {
-                owner,
-                initialized: true,
-                nonce: 0,
+            // Move the last guardian (and its approval bit) into the freed slot
+            let approvals = wallet.recovery_approvals & !(1u8 << index);
+            wallet.recovery_approvals = if approvals & (1u8 << last) != 0 {
+                (approvals & !(1u8 << last)) | (1u8 << index)
+            } else {
+                approvals
             };
-            wallet_data.serialize(&mut &mut wallet_account.data.borrow_mut()[..])?;
-
-            msg!("Wallet initialized for owner: {:?}, PDA: {:?}", owner, pda);
+            wallet.guardians[index] = wallet.guardians[last];
+            wallet.guardians[last] = Pubkey::default();
+            wallet.guardian_count -= 1;
+            store_wallet(&wallet, wallet_info)
         }
-        WalletInstruction::ExecuteTransaction { instruction_data, account_metas } => {
-            msg!("Instruction: ExecuteTransaction");
-            let accounts_iter = &mut accounts.iter();
+        WalletInstruction::InitiateRecovery { new_owner } => {
+            msg!("Instruction: InitiateRecovery");
+            let (wallet_info, mut wallet, index) = load_with_guardian(program_id, accounts)?;
 
-            let wallet_account = next_account_info(accounts_iter)?;
-            let target_program = next_account_info(accounts_iter)?;
+            if wallet.recovery_active {
+                return Err(WalletError::RecoveryInProgress.into());
+            }
+
+            wallet.recovery_new_owner = new_owner;
+            wallet.recovery_approvals = 1u8 << index;
+            wallet.recovery_active = true;
+            store_wallet(&wallet, wallet_info)
+        }
+        WalletInstruction::ApproveRecovery => {
+            msg!("Instruction: ApproveRecovery");
+            let (wallet_info, mut wallet, index) = load_with_guardian(program_id, accounts)?;
 
-            // Ensure the wallet account is initialized and is the PDA for the owner
-            let mut wallet_data = WalletAccount::try_from_slice(&wallet_account.data.borrow())?;
-            if !wallet_data.initialized || wallet_data.owner != wallet_account.owner {
-                return Err(ProgramError::InvalidAccountData);
+            if !wallet.recovery_active {
+                return Err(WalletError::NoRecoveryInProgress.into());
+            }
+            let bit = 1u8 << index;
+            if wallet.recovery_approvals & bit != 0 {
+                return Err(WalletError::AlreadyApproved.into());
             }
 
-            // Reconstruct the instruction to be invoked
-            let instruction = Instruction {
-                program_id: *target_program.key,
-                accounts: account_metas,
-                data: instruction_data,
-            };
+            wallet.recovery_approvals |= bit;
+            complete_recovery_if_approved(&mut wallet);
+            store_wallet(&wallet, wallet_info)
+        }
+        WalletInstruction::FreezeWallet => {
+            msg!("Instruction: FreezeWallet");
+            let (wallet_info, mut wallet, _) = load_with_guardian(program_id, accounts)?;
 
-            // Invoke the instruction signed by the PDA
-            // The wallet_account must be the first signer in the `accounts` slice passed to invoke_signed.
-            // The PDA seeds are derived using the owner and nonce to ensure unique signing authority for each transaction.
-            let (_, bump_seed) = Pubkey::find_program_address(
-                &[wallet_data.owner.as_ref(), &wallet_data.nonce.to_le_bytes()],
-                program_id,
-            );
-            let signer_seeds = &[wallet_data.owner.as_ref(), &wallet_data.nonce.to_le_bytes(), &[bump_seed]];
-
-            invoke_signed(
-                &instruction,
-                accounts,
-                &[signer_seeds],
-            )?;
-
-            // Increment nonce after successful execution
-            wallet_data.nonce += 1;
-            wallet_data.serialize(&mut &mut wallet_account.data.borrow_mut()[..])?;
-        }
-        WalletInstruction::ValidateUserOp { user_op_hash, signature } => {
-            msg!("Instruction: ValidateUserOp");
-            let accounts_iter = &mut accounts.iter();
-            let wallet_account = next_account_info(accounts_iter)?;
+            wallet.is_frozen = true;
+            store_wallet(&wallet, wallet_info)
+        }
+        WalletInstruction::UnfreezeWallet => {
+            msg!("Instruction: UnfreezeWallet");
+            let (wallet_info, mut wallet) = load_with_owner(program_id, accounts)?;
+
+            wallet.is_frozen = false;
+            store_wallet(&wallet, wallet_info)
+        }
+    }
+}
+
+fn initialize_wallet(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    owner: Pubkey,
+    seed: u64,
+) -> ProgramResult {
+    let [payer, wallet_info, system_program, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (pda, bump) = Pubkey::find_program_address(
+        &[owner.as_ref(), &seed.to_le_bytes()],
+        program_id,
+    );
+    if pda != *wallet_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Fails if the account already exists
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            wallet_info.key,
+            Rent::get()?.minimum_balance(WalletAccount::LEN),
+            WalletAccount::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), wallet_info.clone(), system_program.clone()],
+        &[&[owner.as_ref(), &seed.to_le_bytes(), &[bump]]],
+    )?;
+
+    let wallet = WalletAccount {
+        owner,
+        initial_owner: owner,
+        seed,
+        nonce: 0,
+        guardians: [Pubkey::default(); MAX_GUARDIANS],
+        recovery_new_owner: Pubkey::default(),
+        recovery_approvals: 0,
+        recovery_active: false,
+        guardian_count: 0,
+        is_frozen: false,
+        initialized: true,
+        bump,
+    };
+    store_wallet(&wallet, wallet_info)?;
+
+    msg!("Wallet initialized for owner: {}, PDA: {}", owner, pda);
+    Ok(())
+}
+
+fn execute_transaction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    nonce: u64,
+    calls: &[WalletCall],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let [wallet_info, authority, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let mut wallet = load_wallet(program_id, wallet_info)?;
+
+    if wallet.is_frozen {
+        return Err(WalletError::WalletFrozen.into());
+    }
+    if nonce != wallet.nonce {
+        return Err(WalletError::InvalidNonce.into());
+    }
 
-            let mut wallet_data = WalletAccount::try_from_slice(&wallet_account.data.borrow())?;
+    if authority.is_signer {
+        if *authority.key != wallet.owner {
+            return Err(WalletError::InvalidSignature.into());
+        }
+    } else {
+        let message = execute_message(program_id, wallet_info.key, instruction_data);
+        verify_ed25519_instruction(authority, &wallet.owner, &message)?;
+    }
 
-            // Nonce validation
-            // For now, we assume user_op_hash includes the nonce and it's handled implicitly
-            // A more robust implementation might involve a separate nonce check or mapping.
+    // Persist the nonce before any call can re-enter the wallet
+    wallet.nonce += 1;
+    store_wallet(&wallet, wallet_info)?;
 
-            // Signature validation
-            // Verify the signature against the wallet's owner (public key)
-            wallet_data.owner.verify(&user_op_hash, &Signature::new(&signature))
-                .map_err(|_| ProgramError::InvalidSignature)?;
+    let seed_bytes = wallet.seed.to_le_bytes();
+    let signer_seeds: &[&[u8]] = &[wallet.initial_owner.as_ref(), &seed_bytes, &[wallet.bump]];
 
-            msg!("Signature verified for owner: {:?}", wallet_data.owner);
+    for call in calls {
+        if call.value > 0 {
+            let recipient = find_account(accounts, &call.target)?;
+            let remaining = wallet_info.lamports()
+                .checked_sub(call.value)
+                .ok_or(WalletError::InsufficientFunds)?;
 
-            // Increment nonce after successful validation
-            wallet_data.nonce += 1;
-            wallet_data.serialize(&mut &mut wallet_account.data.borrow_mut()[..])?;
+            **wallet_info.try_borrow_mut_lamports()? = remaining;
+            **recipient.try_borrow_mut_lamports()? += call.value;
         }
+
+        if call.data.is_empty() {
+            continue;
+        }
+
+        let instruction = Instruction {
+            program_id: call.target,
+            accounts: call.accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.pubkey,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: call.data.clone(),
+        };
+
+        invoke_signed(&instruction, accounts, &[signer_seeds])?;
+    }
+
+    if wallet_info.lamports() < Rent::get()?.minimum_balance(WalletAccount::LEN) {
+        return Err(WalletError::InsufficientFunds.into());
     }
 
     Ok(())
 }
 
-impl WalletAccount {
-    pub const LEN: usize = 32 + 1 + 8; // Pubkey + bool + u64
-} 
\ No newline at end of file
+fn validate_user_op(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    nonce: u64,
+    user_op_hash: &[u8; 32],
+) -> ProgramResult {
+    let [wallet_info, instructions_sysvar, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let wallet = load_wallet(program_id, wallet_info)?;
+
+    if wallet.is_frozen {
+        return Err(WalletError::WalletFrozen.into());
+    }
+    if nonce != wallet.nonce {
+        return Err(WalletError::InvalidNonce.into());
+    }
+
+    let message = user_op_message(program_id, wallet_info.key, nonce, user_op_hash);
+    verify_ed25519_instruction(instructions_sysvar, &wallet.owner, &message)?;
+
+    msg!("Signature verified for owner: {}", wallet.owner);
+
+    Ok(())
+}
+
+/// Message the owner signs to authorize an `ExecuteTransaction` relayed by
+/// someone else; `instruction_data` is the full encoded instruction
+pub fn execute_message(program_id: &Pubkey, wallet: &Pubkey, instruction_data: &[u8]) -> [u8; 32] {
+    hashv(&[EXECUTE_DOMAIN, program_id.as_ref(), wallet.as_ref(), instruction_data]).to_bytes()
+}
+
+/// Message the owner signs for `ValidateUserOp`
+pub fn user_op_message(
+    program_id: &Pubkey,
+    wallet: &Pubkey,
+    nonce: u64,
+    user_op_hash: &[u8; 32],
+) -> [u8; 32] {
+    hashv(&[
+        USER_OP_DOMAIN,
+        program_id.as_ref(),
+        wallet.as_ref(),
+        &nonce.to_le_bytes(),
+        user_op_hash,
+    ])
+    .to_bytes()
+}
+
+fn complete_recovery_if_approved(wallet: &mut WalletAccount) {
+    if wallet.recovery_approvals.count_ones() < wallet.required_approvals() {
+        return;
+    }
+
+    msg!("Recovery completed, new owner: {}", wallet.recovery_new_owner);
+    wallet.owner = wallet.recovery_new_owner;
+    wallet.recovery_new_owner = Pubkey::default();
+    wallet.recovery_approvals = 0;
+    wallet.recovery_active = false;
+    wallet.nonce += 1; // Invalidate any signed operations
+}
+
+fn load_wallet(program_id: &Pubkey, wallet_info: &AccountInfo) -> Result<WalletAccount, ProgramError> {
+    if wallet_info.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let wallet = WalletAccount::try_from_slice(&wallet_info.try_borrow_data()?)?;
+    if !wallet.initialized {
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    Ok(wallet)
+}
+
+fn store_wallet(wallet: &WalletAccount, wallet_info: &AccountInfo) -> ProgramResult {
+    wallet.serialize(&mut &mut wallet_info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+/// Load the wallet for an instruction whose second account must be the
+/// owner as signer
+fn load_with_owner<'a, 'info>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'info>],
+) -> Result<(&'a AccountInfo<'info>, WalletAccount), ProgramError> {
+    let [wallet_info, owner, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let wallet = load_wallet(program_id, wallet_info)?;
+
+    if !owner.is_signer || *owner.key != wallet.owner {
+        return Err(WalletError::InvalidSignature.into());
+    }
+
+    Ok((wallet_info, wallet))
+}
+
+/// Load the wallet for an instruction whose second account must be one of
+/// its guardians as signer, returning that guardian's slot
+fn load_with_guardian<'a, 'info>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'info>],
+) -> Result<(&'a AccountInfo<'info>, WalletAccount, usize), ProgramError> {
+    let [wallet_info, guardian, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let wallet = load_wallet(program_id, wallet_info)?;
+
+    let index = wallet.guardian_index(guardian.key)
+        .filter(|_| guardian.is_signer)
+        .ok_or(WalletError::UnauthorizedGuardian)?;
+
+    Ok((wallet_info, wallet, index))
+}
+
+fn find_account<'a, 'info>(
+    accounts: &'a [AccountInfo<'info>],
+    key: &Pubkey,
+) -> Result<&'a AccountInfo<'info>, ProgramError> {
+    accounts
+        .iter()
+        .find(|account| account.key == key)
+        .ok_or_else(|| WalletError::MissingCallAccount.into())
+}
+
+/// Require an ed25519 program instruction in this transaction, verifying a
+/// signature by `signer` over `message`. The precompile has already checked
+/// the signature; this confirms which key and message it covered.
+fn verify_ed25519_instruction(
+    instructions_sysvar: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> ProgramResult {
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        if instruction.program_id == ed25519_program::ID
+            && ed25519_instruction_matches(&instruction.data, signer, message)
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+
+    Err(WalletError::InvalidSignature.into())
+}
+
+/// Whether ed25519 program instruction data holds a single signature whose
+/// key and message are inline and equal to the expected ones
+fn ed25519_instruction_matches(data: &[u8], signer: &Pubkey, message: &[u8]) -> bool {
+    // [count: u8, padding: u8] followed by one 14-byte offsets record
+    const HEADER_LEN: usize = 2 + 14;
+    const THIS_INSTRUCTION: u16 = u16::MAX;
+
+    if data.len() < HEADER_LEN || data[0] != 1 {
+        return false;
+    }
+
+    let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+
+    if [read_u16(4), read_u16(8), read_u16(14)].iter().any(|i| *i != THIS_INSTRUCTION) {
+        return false;
+    }
+
+    let slice = |offset: usize, len: usize| data.get(offset..offset.checked_add(len)?);
+    slice(public_key_offset, 32) == Some(signer.as_ref())
+        && message_size == message.len()
+        && slice(message_offset, message_size) == Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        sysvar::{
+            self,
+            instructions::{construct_instructions_data, BorrowedInstruction},
+        },
+    };
+    use std::cell::RefCell;
+    use std::sync::Once;
+
+    const PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+
+    thread_local! {
+        /// (program, signer PDAs) of every CPI made on this thread
+        static INVOCATIONS: RefCell<Vec<(Pubkey, Vec<Pubkey>)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct TestStubs;
+
+    impl SyscallStubs for TestStubs {
+        fn sol_log(&self, _message: &str) {}
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            // SAFETY: the runtime passes a pointer to a `Rent`
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            solana_program::entrypoint::SUCCESS
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            _account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            let signers = signers_seeds
+                .iter()
+                .map(|seeds| Pubkey::create_program_address(seeds, &PROGRAM_ID))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| ProgramError::InvalidSeeds)?;
+            INVOCATIONS.with(|invocations| {
+                invocations.borrow_mut().push((instruction.program_id, signers))
+            });
+            Ok(())
+        }
+    }
+
+    fn install_stubs() {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(TestStubs));
+        });
+        INVOCATIONS.with(|invocations| invocations.borrow_mut().clear());
+    }
+
+    /// Owned data and lamports behind a test `AccountInfo`
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+        is_signer: bool,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey) -> Self {
+            Self { key, owner: Pubkey::default(), lamports: 0, data: Vec::new(), is_signer: false }
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                self.is_signer,
+                true,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+
+    fn wallet_account(owner: Pubkey, guardians: &[Pubkey]) -> TestAccount {
+        let seed = 1u64;
+        let (key, bump) = Pubkey::find_program_address(&[owner.as_ref(), &seed.to_le_bytes()], &PROGRAM_ID);
+        let mut slots = [Pubkey::default(); MAX_GUARDIANS];
+        slots[..guardians.len()].copy_from_slice(guardians);
+        let wallet = WalletAccount {
+            owner,
+            initial_owner: owner,
+            seed,
+            nonce: 0,
+            guardians: slots,
+            recovery_new_owner: Pubkey::default(),
+            recovery_approvals: 0,
+            recovery_active: false,
+            guardian_count: guardians.len() as u8,
+            is_frozen: false,
+            initialized: true,
+            bump,
+        };
+
+        TestAccount {
+            key,
+            owner: PROGRAM_ID,
+            lamports: 1_000_000_000,
+            data: borsh::to_vec(&wallet).unwrap(),
+            is_signer: false,
+        }
+    }
+
+    fn signer(key: Pubkey) -> TestAccount {
+        TestAccount { is_signer: true, ..TestAccount::new(key) }
+    }
+
+    /// Ed25519 program instruction data for one inline signature
+    fn ed25519_data(signer: &Pubkey, message: &[u8]) -> Vec<u8> {
+        let (public_key_offset, signature_offset, message_offset) = (16u16, 48u16, 112u16);
+        let mut data = vec![1, 0];
+        for field in [
+            signature_offset,
+            u16::MAX,
+            public_key_offset,
+            u16::MAX,
+            message_offset,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[0; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    /// Instructions sysvar holding an ed25519 instruction over `message`
+    fn instructions_sysvar(signer: &Pubkey, message: &[u8]) -> TestAccount {
+        let data = ed25519_data(signer, message);
+        let instructions = [BorrowedInstruction {
+            program_id: &ed25519_program::ID,
+            accounts: Vec::new(),
+            data: &data,
+        }];
+        TestAccount {
+            data: construct_instructions_data(&instructions),
+            ..TestAccount::new(sysvar::instructions::ID)
+        }
+    }
+
+    fn stored_wallet(account: &TestAccount) -> WalletAccount {
+        WalletAccount::try_from_slice(&account.data).unwrap()
+    }
+
+    fn execute_data(nonce: u64, calls: Vec<WalletCall>) -> Vec<u8> {
+        borsh::to_vec(&WalletInstruction::ExecuteTransaction { nonce, calls }).unwrap()
+    }
+
+    #[test]
+    fn relayed_execute_requires_the_owners_ed25519_signature() {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let mut wallet = wallet_account(owner, &[]);
+        let data = execute_data(0, Vec::new());
+        let message = execute_message(&PROGRAM_ID, &wallet.key, &data);
+
+        let mut wrong_signer = instructions_sysvar(&Pubkey::new_unique(), &message);
+        let result = process_instruction(&PROGRAM_ID, &[wallet.info(), wrong_signer.info()], &data);
+        assert_eq!(result, Err(WalletError::InvalidSignature.into()));
+
+        let mut other_message = instructions_sysvar(&owner, &[0; 32]);
+        let result = process_instruction(&PROGRAM_ID, &[wallet.info(), other_message.info()], &data);
+        assert_eq!(result, Err(WalletError::InvalidSignature.into()));
+
+        // Sysvar data at another address is not trusted
+        let mut forged = instructions_sysvar(&owner, &message);
+        forged.key = Pubkey::new_unique();
+        assert!(process_instruction(&PROGRAM_ID, &[wallet.info(), forged.info()], &data).is_err());
+
+        let mut signed = instructions_sysvar(&owner, &message);
+        process_instruction(&PROGRAM_ID, &[wallet.info(), signed.info()], &data).unwrap();
+        assert_eq!(stored_wallet(&wallet).nonce, 1);
+
+        // The signed message commits to the nonce, so it cannot be replayed
+        let result = process_instruction(&PROGRAM_ID, &[wallet.info(), signed.info()], &data);
+        assert_eq!(result, Err(WalletError::InvalidNonce.into()));
+    }
+
+    #[test]
+    fn validate_user_op_checks_the_owners_ed25519_signature() {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let mut wallet = wallet_account(owner, &[]);
+        let user_op_hash = [3; 32];
+        let data = borsh::to_vec(&WalletInstruction::ValidateUserOp { nonce: 0, user_op_hash }).unwrap();
+
+        let mut other_hash = instructions_sysvar(&owner, &user_op_message(&PROGRAM_ID, &wallet.key, 0, &[4; 32]));
+        let result = process_instruction(&PROGRAM_ID, &[wallet.info(), other_hash.info()], &data);
+        assert_eq!(result, Err(WalletError::InvalidSignature.into()));
+
+        let mut signed = instructions_sysvar(&owner, &user_op_message(&PROGRAM_ID, &wallet.key, 0, &user_op_hash));
+        process_instruction(&PROGRAM_ID, &[wallet.info(), signed.info()], &data).unwrap();
+        assert_eq!(stored_wallet(&wallet).nonce, 0);
+    }
+
+    #[test]
+    fn validate_user_op_leaves_the_shared_nonce_to_execution() {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let mut wallet = wallet_account(owner, &[]);
+        let user_op_hash = [3; 32];
+        let validate = borsh::to_vec(&WalletInstruction::ValidateUserOp { nonce: 0, user_op_hash }).unwrap();
+        let mut signed = instructions_sysvar(&owner, &user_op_message(&PROGRAM_ID, &wallet.key, 0, &user_op_hash));
+
+        // Replaying the validation cannot burn the nonce
+        for _ in 0..2 {
+            process_instruction(&PROGRAM_ID, &[wallet.info(), signed.info()], &validate).unwrap();
+        }
+        assert_eq!(stored_wallet(&wallet).nonce, 0);
+
+        // The operation still executes under the nonce it was validated for
+        let execute = execute_data(0, Vec::new());
+        let mut relayed = instructions_sysvar(&owner, &execute_message(&PROGRAM_ID, &wallet.key, &execute));
+        process_instruction(&PROGRAM_ID, &[wallet.info(), relayed.info()], &execute).unwrap();
+        assert_eq!(stored_wallet(&wallet).nonce, 1);
+
+        // After which its validation no longer holds
+        let result = process_instruction(&PROGRAM_ID, &[wallet.info(), signed.info()], &validate);
+        assert_eq!(result, Err(WalletError::InvalidNonce.into()));
+    }
+
+    #[test]
+    fn execute_calls_and_transfers_are_signed_by_the_wallet_pda() {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let mut wallet = wallet_account(owner, &[]);
+        let mut owner_account = signer(owner);
+        let mut target = TestAccount::new(Pubkey::new_unique());
+        let call = WalletCall {
+            target: target.key,
+            accounts: vec![CallAccount { pubkey: wallet.key, is_signer: true, is_writable: true }],
+            data: vec![1, 2, 3],
+            value: 500,
+        };
+        let data = execute_data(0, vec![call]);
+
+        process_instruction(
+            &PROGRAM_ID,
+            &[wallet.info(), owner_account.info(), target.info()],
+            &data,
+        )
+        .unwrap();
+
+        assert_eq!(target.lamports, 500);
+        assert_eq!(wallet.lamports, 1_000_000_000 - 500);
+        let invocations = INVOCATIONS.with(|invocations| invocations.borrow().clone());
+        assert_eq!(invocations, vec![(target.key, vec![wallet.key])]);
+
+        // Another key signing as the owner is rejected
+        let mut impostor = signer(Pubkey::new_unique());
+        let data = execute_data(1, Vec::new());
+        let result = process_instruction(&PROGRAM_ID, &[wallet.info(), impostor.info()], &data);
+        assert_eq!(result, Err(WalletError::InvalidSignature.into()));
+    }
+
+    #[test]
+    fn initiating_recovery_never_completes_it() {
+        install_stubs();
+        let guardian = Pubkey::new_unique();
+        let new_owner = Pubkey::new_unique();
+        let mut wallet = wallet_account(Pubkey::new_unique(), &[guardian]);
+        let mut guardian_account = signer(guardian);
+        let data = borsh::to_vec(&WalletInstruction::InitiateRecovery { new_owner }).unwrap();
+
+        process_instruction(&PROGRAM_ID, &[wallet.info(), guardian_account.info()], &data).unwrap();
+
+        let stored = stored_wallet(&wallet);
+        assert!(stored.recovery_active);
+        assert_ne!(stored.owner, new_owner);
+    }
+}
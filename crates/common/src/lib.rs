@@ -0,0 +1,236 @@
+//! NexusDeFi shared types
+//!
+//! Definitions every NexusDeFi program must agree on:
+//! - The canonical `UserOperation` accepted by the wallet and entry point
+//! - Its hash, which covers every field but the signature and is
+//!   domain-separated by entry point program id and cluster
+//! - Errors raised by these shared helpers
+//! - The ERC-4337 v0.7 `PackedUserOperation`, with EVM-identical hashing
+//! - Compute-unit metering, which both programs charge gas by
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::{hash, hashv};
+use solana_program::pubkey;
+
+pub mod packed;
+
+pub use packed::{EvmAddress, EvmPaymaster, PackedUserOperation};
+
+/// Program id of `nexus_entry_point`. Kept here rather than taken from the
+/// entry point crate so the wallet, which the entry point depends on, can
+/// hash operations for it; it must match the entry point's `declare_id!`.
+pub const ENTRY_POINT_PROGRAM_ID: Pubkey = pubkey!("9tPUcx4o8kjtCioPepUqhBozAY3SjTGkgJyxfhxVJEHo");
+
+/// Cluster the programs are built for, playing the role of an EVM chain id
+/// in user operation hashes so a signed operation cannot be replayed on
+/// another cluster. Uses the token list ids (101 mainnet-beta, 102 testnet,
+/// 103 devnet), selected with the `mainnet`, `testnet` or `devnet` feature;
+/// localnet builds use 0.
+pub const CLUSTER_ID: u64 = if cfg!(feature = "mainnet") {
+    101
+} else if cfg!(feature = "testnet") {
+    102
+} else if cfg!(feature = "devnet") {
+    103
+} else {
+    0
+};
+
+/// Pyth oracle program owning the price accounts the wallet reads, for the
+/// cluster selected above. Localnet uses the mainnet program, as price
+/// accounts are usually cloned from mainnet into a test validator.
+pub const PYTH_ORACLE_PROGRAM_ID: Pubkey = if cfg!(feature = "testnet") {
+    pubkey!("8tfDNiaEyrV6Q1U4DEXrEigs9DoDtkugzFbybENEbCDz")
+} else if cfg!(feature = "devnet") {
+    pubkey!("gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s")
+} else {
+    pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH")
+};
+
+/// Domain separator of user operation hashes
+pub const USER_OP_HASH_DOMAIN: &[u8] = b"nexus_user_operation";
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct UserOperation {
+    pub sender: Pubkey,
+    pub nonce: u64,
+    pub init_code: Vec<u8>,
+    pub call_data: Vec<u8>,
+    pub call_gas_limit: u64,
+    pub verification_gas_limit: u64,
+    pub pre_verification_gas: u64,
+    pub max_fee_per_gas: u64,
+    pub max_priority_fee_per_gas: u64,
+    /// Paymaster account followed by data for it; empty when unsponsored
+    pub paymaster_and_data: Vec<u8>,
+    pub signature: [u8; 64],
+}
+
+impl UserOperation {
+    /// Hash signed by the sender's owner. Covers every field but the
+    /// signature; variable-length fields are hashed first so no two
+    /// operations share an encoding.
+    pub fn hash(&self, entry_point: &Pubkey, cluster_id: u64) -> [u8; 32] {
+        hashv(&[
+            USER_OP_HASH_DOMAIN,
+            entry_point.as_ref(),
+            &cluster_id.to_le_bytes(),
+            self.sender.as_ref(),
+            &self.nonce.to_le_bytes(),
+            hash(&self.init_code).as_ref(),
+            hash(&self.call_data).as_ref(),
+            &self.call_gas_limit.to_le_bytes(),
+            &self.verification_gas_limit.to_le_bytes(),
+            &self.pre_verification_gas.to_le_bytes(),
+            &self.max_fee_per_gas.to_le_bytes(),
+            &self.max_priority_fee_per_gas.to_le_bytes(),
+            hash(&self.paymaster_and_data).as_ref(),
+        ])
+        .to_bytes()
+    }
+
+    /// `hash` for the entry point and cluster these programs are built for
+    pub fn canonical_hash(&self) -> [u8; 32] {
+        self.hash(&ENTRY_POINT_PROGRAM_ID, CLUSTER_ID)
+    }
+
+    /// Paymaster named by `paymaster_and_data`, if any
+    pub fn paymaster(&self) -> Result<Option<Pubkey>> {
+        match self.paymaster_and_data.len() {
+            0 => Ok(None),
+            len if len < 32 => err!(CommonError::InvalidPaymasterAndData),
+            _ => {
+                let mut pubkey_bytes = [0u8; 32];
+                pubkey_bytes.copy_from_slice(&self.paymaster_and_data[..32]);
+                Ok(Some(Pubkey::from(pubkey_bytes)))
+            }
+        }
+    }
+
+    /// Factory named by `init_code`, if any: the program deploying the
+    /// sender, followed by data for it
+    pub fn factory(&self) -> Result<Option<Pubkey>> {
+        match self.init_code.len() {
+            0 => Ok(None),
+            len if len < 32 => err!(CommonError::InvalidInitCode),
+            _ => {
+                let mut pubkey_bytes = [0u8; 32];
+                pubkey_bytes.copy_from_slice(&self.init_code[..32]);
+                Ok(Some(Pubkey::from(pubkey_bytes)))
+            }
+        }
+    }
+    
+    /// Gas the operation may use at most: all of its limits
+    pub fn total_gas_limit(&self) -> Result<u64> {
+        self.call_gas_limit
+            .checked_add(self.verification_gas_limit)
+            .and_then(|gas| gas.checked_add(self.pre_verification_gas))
+            .ok_or_else(|| CommonError::GasOverflow.into())
+    }
+
+    /// Lamports per unit of gas. Solana has no per-unit base fee, so as
+    /// under EIP-1559 with a zero base fee this is the priority fee, capped
+    /// by `max_fee_per_gas`.
+    pub fn gas_price(&self) -> u64 {
+        self.max_fee_per_gas.min(self.max_priority_fee_per_gas)
+    }
+    
+    /// Most the operation can cost: its total gas limit at `max_fee_per_gas`
+    pub fn required_prefund(&self) -> Result<u64> {
+        self.total_gas_limit()?
+            .checked_mul(self.max_fee_per_gas)
+            .ok_or_else(|| CommonError::GasOverflow.into())
+    }
+}
+
+/// Compute units left in the transaction's budget; one unit of gas is one
+/// compute unit. Reads the meter through the `sol_remaining_compute_units`
+/// syscall, which solana-program 1.16 does not wrap but validators provide
+/// from 1.17, so the programs must run on a 1.17 or later cluster. Off-chain
+/// builds read 0.
+pub fn remaining_compute_units() -> u64 {
+    #[cfg(target_os = "solana")]
+    {
+        extern "C" {
+            fn sol_remaining_compute_units() -> u64;
+        }
+        // SAFETY: the syscall takes no arguments and only reads the meter
+        unsafe { sol_remaining_compute_units() }
+    }
+    
+    #[cfg(not(target_os = "solana"))]
+    {
+        0
+    }
+}
+
+// Offset past the programs' own error codes, which start at 6000
+#[error_code(offset = 9000)]
+pub enum CommonError {
+    #[msg("paymaster_and_data is too short to name a paymaster")]
+    InvalidPaymasterAndData,
+    #[msg("Gas overflow")]
+    GasOverflow,
+    #[msg("Value does not fit the target field")]
+    ValueOutOfRange,
+    #[msg("init_code is too short to name a factory")]
+    InvalidInitCode,
+    #[msg("Factory must be mapped exactly when the operation names one")]
+    FactoryMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn user_op() -> UserOperation {
+        UserOperation {
+            sender: Pubkey::new_from_array([1; 32]),
+            nonce: 2,
+            init_code: vec![3; 40],
+            call_data: vec![4; 8],
+            call_gas_limit: 5,
+            verification_gas_limit: 6,
+            pre_verification_gas: 7,
+            max_fee_per_gas: 8,
+            max_priority_fee_per_gas: 9,
+            paymaster_and_data: vec![10; 32],
+            signature: [11; 64],
+        }
+    }
+    
+    #[test]
+    fn hash_covers_every_signed_field() {
+        let entry_point = Pubkey::new_from_array([12; 32]);
+        let base = user_op().hash(&entry_point, 1);
+        
+        let changes: [fn(&mut UserOperation); 11] = [
+            |op| op.sender = Pubkey::new_from_array([13; 32]),
+            |op| op.nonce += 1,
+            |op| op.init_code.push(0),
+            |op| op.call_data[0] ^= 1,
+            |op| op.call_gas_limit += 1,
+            |op| op.verification_gas_limit += 1,
+            |op| op.pre_verification_gas += 1,
+            |op| op.max_fee_per_gas += 1,
+            |op| op.max_priority_fee_per_gas += 1,
+            |op| op.paymaster_and_data.clear(),
+            |op| op.paymaster_and_data[31] ^= 1,
+        ];
+        for (index, change) in changes.iter().enumerate() {
+            let mut op = user_op();
+            change(&mut op);
+            assert_ne!(op.hash(&entry_point, 1), base, "field change {index} kept the hash");
+        }
+        
+        // Domain: entry point and cluster
+        assert_ne!(user_op().hash(&Pubkey::new_from_array([14; 32]), 1), base);
+        assert_ne!(user_op().hash(&entry_point, 2), base);
+        
+        // The signature is not signed over
+        let mut op = user_op();
+        op.signature = [0; 64];
+        assert_eq!(op.hash(&entry_point, 1), base);
+    }
+}
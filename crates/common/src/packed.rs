@@ -0,0 +1,363 @@
+//! ERC-4337 v0.7 `PackedUserOperation`, for targeting the EVM EntryPoint
+//! with the same operation an SVM wallet would accept.
+//!
+//! `PackedUserOperation::user_op_hash` is byte-for-byte the v0.7
+//! `EntryPoint.getUserOpHash`: `keccak256(abi.encode(keccak256(encode(op)),
+//! entryPoint, block.chainid))` where `encode` is `UserOperationLib.encode`.
+//! uint256 values are held as 32-byte big-endian words.
+//!
+//! The two forms of an operation do not share a hash or a signature: the
+//! EVM account's owner signs `user_op_hash` with secp256k1 (65 bytes), the
+//! SVM wallet's owner signs `UserOperation::hash` with ed25519 (64 bytes).
+//! Converting therefore never carries the signature over; the converted
+//! operation comes back unsigned and must be signed for its own chain.
+//! Factories and paymasters are named by 20-byte addresses on the EVM and
+//! 32-byte keys on the SVM, so the counterpart of each must be given.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+
+use crate::{CommonError, UserOperation};
+
+/// 20-byte EVM address
+pub type EvmAddress = [u8; 20];
+
+/// Big-endian uint256 / bytes32 word
+pub type Word = [u8; 32];
+
+const ADDRESS_LEN: usize = 20;
+/// `paymasterAndData` prefix: paymaster, verification and post-op gas limits
+const PAYMASTER_DATA_OFFSET: usize = ADDRESS_LEN + 16 + 16;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PackedUserOperation {
+    pub sender: EvmAddress,
+    /// 192-bit key followed by a 64-bit sequence number
+    pub nonce: Word,
+    /// `factory` followed by `factoryData`; empty for deployed accounts
+    pub init_code: Vec<u8>,
+    pub call_data: Vec<u8>,
+    /// `verificationGasLimit` (high 16 bytes) and `callGasLimit` (low 16)
+    pub account_gas_limits: Word,
+    pub pre_verification_gas: Word,
+    /// `maxPriorityFeePerGas` (high 16 bytes) and `maxFeePerGas` (low 16)
+    pub gas_fees: Word,
+    /// `paymaster`, `paymasterVerificationGasLimit` (16 bytes),
+    /// `paymasterPostOpGasLimit` (16 bytes) then `paymasterData`; empty when
+    /// unsponsored
+    pub paymaster_and_data: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// Paymaster fields of a `PackedUserOperation`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvmPaymaster {
+    pub address: EvmAddress,
+    pub verification_gas_limit: u128,
+    pub post_op_gas_limit: u128,
+}
+
+impl PackedUserOperation {
+    pub fn factory(&self) -> Option<EvmAddress> {
+        self.init_code.get(..ADDRESS_LEN).map(|factory| factory.try_into().unwrap())
+    }
+
+    pub fn factory_data(&self) -> &[u8] {
+        self.init_code.get(ADDRESS_LEN..).unwrap_or_default()
+    }
+
+    pub fn verification_gas_limit(&self) -> u128 {
+        high_u128(&self.account_gas_limits)
+    }
+
+    pub fn call_gas_limit(&self) -> u128 {
+        low_u128(&self.account_gas_limits)
+    }
+
+    pub fn max_priority_fee_per_gas(&self) -> u128 {
+        high_u128(&self.gas_fees)
+    }
+
+    pub fn max_fee_per_gas(&self) -> u128 {
+        low_u128(&self.gas_fees)
+    }
+
+    /// Paymaster and its gas limits, if sponsored
+    pub fn paymaster(&self) -> Result<Option<EvmPaymaster>> {
+        if self.paymaster_and_data.is_empty() {
+            return Ok(None);
+        }
+        require!(
+            self.paymaster_and_data.len() >= PAYMASTER_DATA_OFFSET,
+            CommonError::InvalidPaymasterAndData
+        );
+
+        let data = &self.paymaster_and_data;
+        Ok(Some(EvmPaymaster {
+            address: data[..ADDRESS_LEN].try_into().unwrap(),
+            verification_gas_limit: u128::from_be_bytes(data[ADDRESS_LEN..ADDRESS_LEN + 16].try_into().unwrap()),
+            post_op_gas_limit: u128::from_be_bytes(data[ADDRESS_LEN + 16..PAYMASTER_DATA_OFFSET].try_into().unwrap()),
+        }))
+    }
+
+    pub fn paymaster_data(&self) -> &[u8] {
+        self.paymaster_and_data.get(PAYMASTER_DATA_OFFSET..).unwrap_or_default()
+    }
+
+    /// `UserOperationLib.encode`: the ABI encoding of the static fields and
+    /// the hashes of the dynamic ones, without the signature
+    pub fn encode(&self) -> Vec<u8> {
+        [
+            address_word(&self.sender),
+            self.nonce,
+            keccak::hash(&self.init_code).to_bytes(),
+            keccak::hash(&self.call_data).to_bytes(),
+            self.account_gas_limits,
+            self.pre_verification_gas,
+            self.gas_fees,
+            keccak::hash(&self.paymaster_and_data).to_bytes(),
+        ]
+        .concat()
+    }
+
+    /// `UserOperationLib.hash`
+    pub fn hash(&self) -> [u8; 32] {
+        keccak::hash(&self.encode()).to_bytes()
+    }
+
+    /// `EntryPoint.getUserOpHash` of the EntryPoint at `entry_point` on
+    /// chain `chain_id`
+    pub fn user_op_hash(&self, entry_point: &EvmAddress, chain_id: u64) -> [u8; 32] {
+        keccak::hashv(&[
+            &self.hash(),
+            &address_word(entry_point),
+            &u128_word(chain_id as u128),
+        ])
+        .to_bytes()
+    }
+
+    /// Unsigned SVM form of this operation for the wallet at `sender`.
+    /// `factory` replaces the EVM factory, which must be given exactly when
+    /// the operation has one; `factoryData` follows it. The operation is
+    /// sponsored by `paymaster` if it is, with `paymasterData` after the
+    /// paymaster key; the EVM paymaster gas limits have no SVM field.
+    pub fn to_user_operation(
+        &self,
+        sender: Pubkey,
+        factory: Option<Pubkey>,
+        paymaster: Option<Pubkey>,
+    ) -> Result<UserOperation> {
+        require!(
+            self.init_code.is_empty() || self.init_code.len() >= ADDRESS_LEN,
+            CommonError::InvalidInitCode
+        );
+        let init_code = match (self.factory(), factory) {
+            (Some(_), Some(factory)) => [factory.as_ref(), self.factory_data()].concat(),
+            (None, None) => Vec::new(),
+            _ => return err!(CommonError::FactoryMismatch),
+        };
+        let paymaster_and_data = match paymaster {
+            Some(paymaster) => [paymaster.as_ref(), self.paymaster_data()].concat(),
+            None => Vec::new(),
+        };
+
+        Ok(UserOperation {
+            sender,
+            nonce: u64_from_word(&self.nonce)?,
+            init_code,
+            call_data: self.call_data.clone(),
+            call_gas_limit: to_u64(self.call_gas_limit())?,
+            verification_gas_limit: to_u64(self.verification_gas_limit())?,
+            pre_verification_gas: u64_from_word(&self.pre_verification_gas)?,
+            max_fee_per_gas: to_u64(self.max_fee_per_gas())?,
+            max_priority_fee_per_gas: to_u64(self.max_priority_fee_per_gas())?,
+            paymaster_and_data,
+            signature: [0; 64],
+        })
+    }
+}
+
+impl UserOperation {
+    /// Unsigned EVM form of this operation for the account at `sender`.
+    /// `factory` replaces the SVM factory key, which must be given exactly
+    /// when the operation has one; the data after that key becomes
+    /// `factoryData`. The operation is sponsored by `paymaster` if given,
+    /// with the data after the SVM paymaster key as `paymasterData`; the
+    /// nonce uses key 0.
+    pub fn to_packed(
+        &self,
+        sender: EvmAddress,
+        factory: Option<EvmAddress>,
+        paymaster: Option<EvmPaymaster>,
+    ) -> Result<PackedUserOperation> {
+        let init_code = match (self.factory()?, factory) {
+            (Some(_), Some(factory)) => [&factory[..], &self.init_code[32..]].concat(),
+            (None, None) => Vec::new(),
+            _ => return err!(CommonError::FactoryMismatch),
+        };
+        let paymaster_and_data = match paymaster {
+            Some(paymaster) => {
+                let data = self.paymaster_and_data.get(32..).unwrap_or_default();
+                [
+                    &paymaster.address[..],
+                    &paymaster.verification_gas_limit.to_be_bytes(),
+                    &paymaster.post_op_gas_limit.to_be_bytes(),
+                    data,
+                ]
+                .concat()
+            }
+            None => Vec::new(),
+        };
+
+        Ok(PackedUserOperation {
+            sender,
+            nonce: u128_word(self.nonce as u128),
+            init_code,
+            call_data: self.call_data.clone(),
+            account_gas_limits: pack_u128s(self.verification_gas_limit as u128, self.call_gas_limit as u128),
+            pre_verification_gas: u128_word(self.pre_verification_gas as u128),
+            gas_fees: pack_u128s(self.max_priority_fee_per_gas as u128, self.max_fee_per_gas as u128),
+            paymaster_and_data,
+            signature: Vec::new(),
+        })
+    }
+}
+
+/// bytes32 holding `high` in its first 16 bytes and `low` in its last 16,
+/// as in `accountGasLimits` and `gasFees`
+pub fn pack_u128s(high: u128, low: u128) -> Word {
+    let mut word = [0u8; 32];
+    word[..16].copy_from_slice(&high.to_be_bytes());
+    word[16..].copy_from_slice(&low.to_be_bytes());
+    word
+}
+
+fn high_u128(word: &Word) -> u128 {
+    u128::from_be_bytes(word[..16].try_into().unwrap())
+}
+
+fn low_u128(word: &Word) -> u128 {
+    u128::from_be_bytes(word[16..].try_into().unwrap())
+}
+
+fn address_word(address: &EvmAddress) -> Word {
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(address);
+    word
+}
+
+fn u128_word(value: u128) -> Word {
+    pack_u128s(0, value)
+}
+
+fn u64_from_word(word: &Word) -> Result<u64> {
+    require!(
+        word[..24].iter().all(|byte| *byte == 0),
+        CommonError::ValueOutOfRange
+    );
+    Ok(u64::from_be_bytes(word[24..].try_into().unwrap()))
+}
+
+fn to_u64(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| CommonError::ValueOutOfRange.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// v0.7 EntryPoint deployment address
+    const ENTRY_POINT: EvmAddress = [
+        0x00, 0x00, 0x00, 0x00, 0x71, 0x72, 0x7d, 0xe2, 0x2e, 0x5e,
+        0x9d, 0x8b, 0xaf, 0x0e, 0xda, 0xc6, 0xf3, 0x7d, 0xa0, 0x32,
+    ];
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn deployed_op() -> PackedUserOperation {
+        PackedUserOperation {
+            sender: [0x11; 20],
+            nonce: u128_word(5),
+            init_code: Vec::new(),
+            call_data: hex("deadbeef"),
+            account_gas_limits: pack_u128s(100_000, 200_000),
+            pre_verification_gas: u128_word(50_000),
+            gas_fees: pack_u128s(1_000_000_000, 2_000_000_000),
+            paymaster_and_data: Vec::new(),
+            signature: vec![0x1b; 65],
+        }
+    }
+
+    fn sponsored_deployment_op() -> PackedUserOperation {
+        let paymaster_and_data = [
+            &[0x33; 20][..],
+            &60_000u128.to_be_bytes(),
+            &30_000u128.to_be_bytes(),
+            &hex("abcd"),
+        ]
+        .concat();
+        PackedUserOperation {
+            sender: [0x44; 20],
+            // Key 1, sequence 7
+            nonce: u128_word((1 << 64) | 7),
+            init_code: [&[0x22; 20][..], &hex("c0ffee")].concat(),
+            call_data: Vec::new(),
+            account_gas_limits: pack_u128s(150_000, 300_000),
+            pre_verification_gas: u128_word(21_000),
+            gas_fees: pack_u128s(3, 40),
+            paymaster_and_data,
+            signature: Vec::new(),
+        }
+    }
+
+    // Expected values follow `UserOperationLib.hash` and
+    // `EntryPoint.getUserOpHash` of the v0.7 EntryPoint, computed with a
+    // separate Keccak-256 and ABI encoding
+    #[test]
+    fn hashes_match_the_v07_entry_point() {
+        let op = deployed_op();
+        assert_eq!(op.hash().to_vec(), hex("fae5eb89fb375f37f0a67688840aaec74b0d989beab6f43888d71d2c1c9ff02f"));
+        assert_eq!(
+            op.user_op_hash(&ENTRY_POINT, 1).to_vec(),
+            hex("322f9f27b0f2126bc35802c403677656da3a472ed08bf6636738f43521853203")
+        );
+
+        let op = sponsored_deployment_op();
+        assert_eq!(op.hash().to_vec(), hex("44d8052a8d8d4a702af4da4155b183c1c1d62dde6c51aec098d575dee3147388"));
+        assert_eq!(
+            op.user_op_hash(&ENTRY_POINT, 11_155_111).to_vec(),
+            hex("9a7de74a9d45625082cdd195dfcfb1df08122b24875a761a01d7688149368615")
+        );
+    }
+
+    #[test]
+    fn conversion_maps_factories_and_drops_signatures() {
+        // SVM nonces have no key
+        let op = PackedUserOperation { nonce: u128_word(7), ..sponsored_deployment_op() };
+        let factory = Pubkey::new_unique();
+        let paymaster = Pubkey::new_unique();
+
+        assert!(op.to_user_operation(Pubkey::new_unique(), None, Some(paymaster)).is_err());
+        assert!(deployed_op().to_user_operation(Pubkey::new_unique(), Some(factory), None).is_err());
+
+        // A 65-byte EVM signature converts to an unsigned SVM operation
+        let svm = deployed_op().to_user_operation(Pubkey::new_unique(), None, None).unwrap();
+        assert_eq!(svm.signature, [0; 64]);
+
+        let svm = op.to_user_operation(Pubkey::new_unique(), Some(factory), Some(paymaster)).unwrap();
+        assert_eq!(svm.factory().unwrap(), Some(factory));
+        assert_eq!(svm.init_code[32..], hex("c0ffee"));
+        assert_eq!(svm.paymaster().unwrap(), Some(paymaster));
+        assert_eq!(svm.nonce, 7);
+
+        let paymaster = op.paymaster().unwrap();
+        let evm = svm.to_packed(op.sender, op.factory(), paymaster).unwrap();
+        assert!(evm.signature.is_empty());
+        assert_eq!(evm.factory(), op.factory());
+        assert_eq!(evm.factory_data(), op.factory_data());
+        assert_eq!(evm.paymaster_and_data, op.paymaster_and_data);
+        assert!(svm.to_packed(op.sender, None, paymaster).is_err());
+    }
+}
@@ -6,8 +6,22 @@
 //! - Paymaster integration for sponsored transactions
 //! - Bundler support for batch operations
 //! - Stake management for paymasters
+//! - Signature aggregators verifying a group of operations in one call
+//! - A registry of staked bundlers, throttled or banned on poor records
+//! - Governance: timelocked parameter updates, two-step authority transfer
+//!   and an emergency pause
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    program::{invoke, set_return_data},
+    sysvar,
+};
+use nexus_paymaster::{program::NexusPaymaster, Paymaster, PostOpMode};
+pub use nexus_common::UserOperation;
+use nexus_common::remaining_compute_units;
+use nexus_wallet::{program::NexusWallet, UserOpStatus};
 
 declare_id!("9tPUcx4o8kjtCioPepUqhBozAY3SjTGkgJyxfhxVJEHo");
 
@@ -24,6 +38,14 @@ pub mod nexus_entry_point {
         entry_point.total_paymasters = 0;
         entry_point.min_stake = 1_000_000_000; // 1 SOL minimum stake
         entry_point.unstake_delay = 86400; // 24 hours
+        entry_point.min_bundler_stake = 1_000_000_000; // 1 SOL
+        entry_point.min_bundler_success_bps = 9_000; // 90%
+        entry_point.require_registered_bundlers = false;
+        entry_point.timelock_delay = 0;
+        entry_point.pending_change = None;
+        entry_point.pending_authority = Pubkey::default();
+        entry_point.authority_transfer_time = 0;
+        entry_point.paused = false;
         
         emit!(EntryPointInitialized {
             entry_point: entry_point.key(),
@@ -33,95 +55,266 @@ pub mod nexus_entry_point {
         Ok(())
     }
 
-    /// Handle a batch of user operations
-    pub fn handle_ops(
-        ctx: Context<HandleOps>,
+    /// Handle a batch of user operations. Each operation is checked by its
+    /// wallet (`validate_user_op`) and, when `paymaster_and_data` names a
+    /// paymaster, by that paymaster's `validate_paymaster_user_op` before the
+    /// wallet executes it; `post_op` then settles the paymaster's cost.
+    ///
+    /// `op_accounts[i]` describes operation `i`'s slice of the remaining
+    /// accounts (see `OpAccounts`). Operations rejected during validation
+    /// are skipped. A call failing during execution aborts the whole batch,
+    /// as a failed CPI cannot be caught.
+    ///
+    /// Each executed operation is charged for the compute units it actually
+    /// consumed (see `GasCharge`), paid from its payer's deposit to
+    /// `beneficiary`.
+    ///
+    /// When the bundler is registered, its success and failure counts are
+    /// updated; a banned or throttled bundler is limited as in
+    /// `check_bundler`.
+    pub fn handle_ops<'info>(
+        ctx: Context<'_, '_, '_, 'info, HandleOps<'info>>,
         user_ops: Vec<UserOperation>,
-        beneficiary: Pubkey,
+        op_accounts: Vec<OpAccounts>,
     ) -> Result<()> {
-        let entry_point = &mut ctx.accounts.entry_point;
-        let bundler = &ctx.accounts.bundler;
-        
-        let mut successful_ops = 0;
-        let mut total_gas_used = 0;
-        
-        for user_op in user_ops.iter() {
-            match process_user_operation(user_op, bundler.key()) {
-                Ok(gas_used) => {
-                    successful_ops += 1;
-                    total_gas_used += gas_used;
-                    
-                    emit!(UserOperationEvent {
-                        user_op_hash: calculate_user_op_hash(user_op)?,
-                        sender: user_op.sender,
-                        paymaster: extract_paymaster(&user_op.paymaster_and_data),
-                        nonce: user_op.nonce,
-                        success: true,
-                        actual_gas_cost: gas_used,
-                        actual_gas_used: gas_used,
-                    });
-                }
-                Err(_) => {
-                    emit!(UserOperationEvent {
-                        user_op_hash: calculate_user_op_hash(user_op)?,
-                        sender: user_op.sender,
-                        paymaster: extract_paymaster(&user_op.paymaster_and_data),
-                        nonce: user_op.nonce,
-                        success: false,
-                        actual_gas_cost: 0,
-                        actual_gas_used: 0,
-                    });
-                }
-            }
-        }
+        check_bundler(ctx.accounts, user_ops.len())?;
         
-        entry_point.total_operations += user_ops.len() as u64;
+        let mut totals = BatchTotals::default();
+        let (ops, _) = OpAccountInfos::split_all(ctx.remaining_accounts, &user_ops, &op_accounts)?;
+        let entry_point_bump = *ctx.bumps.get("entry_point").unwrap();
+        process_user_operations(ctx.accounts, &user_ops, &ops, None, entry_point_bump, &mut totals)?;
         
-        emit!(BatchProcessed {
-            beneficiary,
-            total_operations: user_ops.len() as u8,
-            successful_operations: successful_ops,
-            total_gas_used,
-        });
+        finish_batch(ctx.accounts, &totals)
+    }
+    
+    /// Handle user operations grouped by signature aggregator, as
+    /// ERC-4337's `handleAggregatedOps`. Each group's signatures are
+    /// verified once by the aggregator program's `validate_signatures`
+    /// (see `AggregatedUserOp`); its wallets then execute the operations
+    /// without checking signatures themselves. Every operation in a group
+    /// must come from a wallet that has set the group's aggregator.
+    ///
+    /// Each group takes, in order, the aggregator program, its
+    /// `aggregator_accounts` accounts, then its operations' accounts as in
+    /// `handle_ops`. A group the aggregator rejects aborts the whole call.
+    pub fn handle_aggregated_ops<'info>(
+        ctx: Context<'_, '_, '_, 'info, HandleOps<'info>>,
+        ops_per_aggregator: Vec<UserOpsPerAggregator>,
+    ) -> Result<()> {
+        check_bundler(
+            ctx.accounts,
+            ops_per_aggregator.iter().map(|group| group.user_ops.len()).sum(),
+        )?;
         
-        Ok(())
+        let mut totals = BatchTotals::default();
+        let entry_point_bump = *ctx.bumps.get("entry_point").unwrap();
+        let mut remaining_accounts = ctx.remaining_accounts;
+        
+        for group in ops_per_aggregator.iter() {
+            let aggregator_len = 1 + group.aggregator_accounts as usize;
+            require!(remaining_accounts.len() >= aggregator_len, EntryPointError::InvalidOpAccounts);
+            let (aggregator_accounts, rest) = remaining_accounts.split_at(aggregator_len);
+            let (ops, rest) = OpAccountInfos::split_all(rest, &group.user_ops, &group.op_accounts)?;
+            remaining_accounts = rest;
+            
+            let aggregator = &aggregator_accounts[0];
+            require!(
+                aggregator.key() == group.aggregator && aggregator.executable,
+                EntryPointError::InvalidAggregator
+            );
+            
+            let mut signed_ops = Vec::with_capacity(ops.len());
+            for (user_op, accounts) in group.user_ops.iter().zip(ops.iter()) {
+                let signer = AccountLoader::<nexus_wallet::Wallet>::try_from(accounts.wallet)?
+                    .load()?
+                    .owner;
+                signed_ops.push(AggregatedUserOp {
+                    user_op_hash: user_op.canonical_hash(),
+                    sender: user_op.sender,
+                    signer,
+                });
+            }
+            validate_signatures(aggregator, &aggregator_accounts[1..], signed_ops, &group.signature)?;
+            
+            emit!(SignaturesAggregated {
+                aggregator: group.aggregator,
+                operations: group.user_ops.len() as u8,
+            });
+            
+            process_user_operations(
+                ctx.accounts,
+                &group.user_ops,
+                &ops,
+                Some(group.aggregator),
+                entry_point_bump,
+                &mut totals,
+            )?;
+        }
+        
+        finish_batch(ctx.accounts, &totals)
     }
 
-    /// Simulate validation of a user operation
-    pub fn simulate_validation(
-        _ctx: Context<SimulateValidation>,
+    /// Run `handle_ops`' validation of one operation, whose accounts are
+    /// the remaining accounts laid out as `op_accounts`, without executing
+    /// it. The `ValidationSimulation` is returned as return data; nothing
+    /// is written, as validation only reads state.
+    pub fn simulate_validation<'info>(
+        ctx: Context<'_, '_, '_, 'info, SimulateValidation<'info>>,
         user_op: UserOperation,
-    ) -> Result<()> {
-        let validation_result = validate_user_operation(&user_op)?;
+        op_accounts: OpAccounts,
+    ) -> Result<ValidationSimulation> {
+        let (accounts, _) = OpAccountInfos::split(ctx.remaining_accounts, &user_op, &op_accounts)?;
+        let user_op_hash = user_op.canonical_hash();
+        
+        let validation = validate_operation(
+            &ctx.accounts.entry_point,
+            &ctx.accounts.wallet_program,
+            ctx.accounts.paymaster_program.as_deref(),
+            &ctx.accounts.instructions_sysvar,
+            &accounts,
+            &user_op,
+            &user_op_hash,
+        )?;
+        let (validation_result, return_info, aggregator) = match validation {
+            Ok(validation) => {
+                let pre_op_gas = validation.validation_start
+                    .saturating_sub(remaining_compute_units())
+                    .min(user_op.verification_gas_limit)
+                    .saturating_add(user_op.pre_verification_gas);
+                let return_info = ReturnInfo {
+                    pre_op_gas,
+                    prefund: user_op.required_prefund()?,
+                    valid_after: validation.valid_after,
+                    valid_until: validation.valid_until,
+                    paymaster_context: validation.paymaster
+                        .map(|(_, _, context)| context)
+                        .unwrap_or_default(),
+                };
+                (ValidationResult::Valid, Some(return_info), validation.aggregator)
+            }
+            Err(validation_result) => (validation_result, None, None),
+        };
+        
+        let paymaster_info = match accounts.paymaster {
+            Some((paymaster, _, paymaster_stake)) => {
+                let owner = Account::<Paymaster>::try_from(paymaster)?.owner;
+                Some(stake_info(Some(paymaster_stake), owner)?)
+            }
+            None => None,
+        };
+        let factory_info = match user_op.factory()? {
+            Some(factory) => Some(stake_info(ctx.accounts.factory_stake.as_deref(), factory)?),
+            None => None,
+        };
         
         emit!(ValidationSimulated {
-            user_op_hash: calculate_user_op_hash(&user_op)?,
+            user_op_hash,
             sender: user_op.sender,
             validation_result,
         });
         
-        Ok(())
+        Ok(ValidationSimulation {
+            user_op_hash,
+            validation_result,
+            return_info,
+            sender_info: stake_info(ctx.accounts.sender_stake.as_deref(), user_op.sender)?,
+            factory_info,
+            paymaster_info,
+            aggregator,
+        })
+    }
+    
+    /// Validate and execute one operation exactly as `handle_ops` would,
+    /// then fail with `SimulationComplete` so that nothing is persisted.
+    /// The `ExecutionSimulation` is set as return data before failing. A
+    /// call failing during execution fails the simulation with its own
+    /// error instead.
+    pub fn simulate_handle_op<'info>(
+        ctx: Context<'_, '_, '_, 'info, HandleOps<'info>>,
+        user_op: UserOperation,
+        op_accounts: OpAccounts,
+    ) -> Result<()> {
+        let (accounts, _) = OpAccountInfos::split(ctx.remaining_accounts, &user_op, &op_accounts)?;
+        let user_op_hash = user_op.canonical_hash();
+        let entry_point_bump = *ctx.bumps.get("entry_point").unwrap();
+        
+        let outcome = process_user_operation(
+            ctx.accounts,
+            &accounts,
+            &user_op,
+            &user_op_hash,
+            None,
+            entry_point_bump,
+        )?;
+        let simulation = match outcome {
+            Ok(charge) => ExecutionSimulation {
+                user_op_hash,
+                validation_result: ValidationResult::Valid,
+                executed: true,
+                gas_used: charge.gas_used,
+                actual_gas_cost: charge.cost,
+            },
+            Err(validation_result) => ExecutionSimulation {
+                user_op_hash,
+                validation_result,
+                executed: false,
+                gas_used: 0,
+                actual_gas_cost: 0,
+            },
+        };
+        
+        set_return_data(&simulation.try_to_vec()?);
+        err!(EntryPointError::SimulationComplete)
     }
 
-    /// Add stake for a paymaster
+    /// Add stake for a paymaster, or top up an existing stake. `amount`
+    /// lamports move into the stake PDA, the total must reach `min_stake`,
+    /// and stake that was unlocking is locked again.
     pub fn add_stake(
         ctx: Context<AddStake>,
+        amount: u64,
         unstake_delay: i64,
     ) -> Result<()> {
-        let paymaster_stake = &mut ctx.accounts.paymaster_stake;
         let entry_point = &ctx.accounts.entry_point;
-        let paymaster = &ctx.accounts.paymaster;
-        let deposit = &ctx.accounts.deposit;
+        let paymaster_stake = &ctx.accounts.paymaster_stake;
         
         require!(unstake_delay >= entry_point.unstake_delay, EntryPointError::InvalidUnstakeDelay);
+        require!(unstake_delay <= MAX_DELAY, EntryPointError::InvalidUnstakeDelay);
+        // The delay a stake was locked with cannot be shortened
+        require!(
+            unstake_delay >= paymaster_stake.unstake_delay,
+            EntryPointError::InvalidUnstakeDelay
+        );
+        
+        let stake = paymaster_stake.stake
+            .checked_add(amount)
+            .ok_or(EntryPointError::StakeOverflow)?;
+        require!(stake >= entry_point.min_stake, EntryPointError::InsufficientStake);
+        
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.paymaster.to_account_info(),
+                    to: ctx.accounts.paymaster_stake.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        
+        let paymaster = ctx.accounts.paymaster.key();
+        let paymaster_stake = &mut ctx.accounts.paymaster_stake;
+        if paymaster_stake.paymaster == Pubkey::default() {
+            ctx.accounts.entry_point.total_paymasters += 1;
+        }
         
-        paymaster_stake.paymaster = paymaster.key();
-        paymaster_stake.stake = **deposit.lamports.borrow();
+        paymaster_stake.paymaster = paymaster;
+        paymaster_stake.stake = stake;
         paymaster_stake.unstake_delay = unstake_delay;
         paymaster_stake.withdraw_time = 0;
         
         emit!(StakeAdded {
-            paymaster: paymaster.key(),
+            paymaster,
             stake: paymaster_stake.stake,
             unstake_delay,
         });
@@ -129,12 +322,18 @@ pub mod nexus_entry_point {
         Ok(())
     }
 
-    /// Unlock stake for withdrawal
+    /// Unlock stake for withdrawal. Unlocking stake no longer counts as
+    /// staked for sponsoring operations.
     pub fn unlock_stake(ctx: Context<UnlockStake>) -> Result<()> {
         let paymaster_stake = &mut ctx.accounts.paymaster_stake;
         
+        require!(paymaster_stake.stake > 0, EntryPointError::NotStaked);
+        require!(paymaster_stake.withdraw_time == 0, EntryPointError::StakeUnlocking);
+        
         let current_time = Clock::get()?.unix_timestamp;
-        paymaster_stake.withdraw_time = current_time + paymaster_stake.unstake_delay;
+        paymaster_stake.withdraw_time = current_time
+            .checked_add(paymaster_stake.unstake_delay)
+            .ok_or(EntryPointError::TimestampOverflow)?;
         
         emit!(StakeUnlocked {
             paymaster: paymaster_stake.paymaster,
@@ -144,11 +343,9 @@ pub mod nexus_entry_point {
         Ok(())
     }
 
-    /// Withdraw unlocked stake
-    pub fn withdraw_stake(
-        ctx: Context<WithdrawStake>,
-        withdraw_address: Pubkey,
-    ) -> Result<()> {
+    /// Withdraw unlocked stake to `withdraw_to`. The stake account is
+    /// closed and its rent returned to the paymaster.
+    pub fn withdraw_stake(ctx: Context<WithdrawStake>) -> Result<()> {
         let paymaster_stake = &ctx.accounts.paymaster_stake;
         let current_time = Clock::get()?.unix_timestamp;
         
@@ -158,32 +355,355 @@ pub mod nexus_entry_point {
         );
         
         let amount = paymaster_stake.stake;
+        **paymaster_stake.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.withdraw_to.try_borrow_mut_lamports()? += amount;
         
         emit!(StakeWithdrawn {
             paymaster: paymaster_stake.paymaster,
-            withdraw_address,
+            withdraw_address: ctx.accounts.withdraw_to.key(),
             amount,
         });
         
         Ok(())
     }
 
-    /// Get deposit information for an account
+    /// Slash part of a paymaster's stake to `recipient` (authority only).
+    /// Stake left below `min_stake` no longer counts as staked until it is
+    /// topped up.
+    pub fn slash_stake(ctx: Context<SlashStake>, amount: u64) -> Result<()> {
+        let paymaster_stake = &mut ctx.accounts.paymaster_stake;
+        
+        require!(amount <= paymaster_stake.stake, EntryPointError::InsufficientStake);
+        
+        paymaster_stake.stake -= amount;
+        **paymaster_stake.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.recipient.try_borrow_mut_lamports()? += amount;
+        
+        emit!(StakeSlashed {
+            paymaster: paymaster_stake.paymaster,
+            recipient: ctx.accounts.recipient.key(),
+            amount,
+            remaining_stake: paymaster_stake.stake,
+        });
+        
+        Ok(())
+    }
+    
+    /// Register the signer as a bundler, locking `stake` (at least
+    /// `min_bundler_stake`) in its registration
+    pub fn register_bundler(ctx: Context<RegisterBundler>, stake: u64) -> Result<()> {
+        require!(
+            stake >= ctx.accounts.entry_point.min_bundler_stake,
+            EntryPointError::InsufficientStake
+        );
+        
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.bundler.to_account_info(),
+                    to: ctx.accounts.bundler_registration.to_account_info(),
+                },
+            ),
+            stake,
+        )?;
+        
+        let registration = &mut ctx.accounts.bundler_registration;
+        registration.bundler = ctx.accounts.bundler.key();
+        registration.stake = stake;
+        registration.withdraw_time = 0;
+        registration.status = BundlerStatus::Active;
+        registration.successful_operations = 0;
+        registration.failed_operations = 0;
+        
+        emit!(BundlerRegistered {
+            bundler: registration.bundler,
+            stake,
+        });
+        
+        Ok(())
+    }
+    
+    /// Start unregistering a bundler. Its stake can be withdrawn after
+    /// `unstake_delay`, and it can no longer submit operations meanwhile.
+    /// Banned bundlers cannot unregister until the authority lifts the ban.
+    pub fn unregister_bundler(ctx: Context<UnregisterBundler>) -> Result<()> {
+        let unstake_delay = ctx.accounts.entry_point.unstake_delay;
+        let registration = &mut ctx.accounts.bundler_registration;
+        
+        require!(registration.status != BundlerStatus::Banned, EntryPointError::BundlerBanned);
+        require!(registration.withdraw_time == 0, EntryPointError::StakeUnlocking);
+        
+        registration.withdraw_time = Clock::get()?.unix_timestamp
+            .checked_add(unstake_delay)
+            .ok_or(EntryPointError::TimestampOverflow)?;
+        
+        emit!(BundlerUnregistered {
+            bundler: registration.bundler,
+            withdraw_time: registration.withdraw_time,
+        });
+        
+        Ok(())
+    }
+    
+    /// Withdraw an unregistered bundler's stake to `withdraw_to`, closing
+    /// its registration
+    pub fn withdraw_bundler_stake(ctx: Context<WithdrawBundlerStake>) -> Result<()> {
+        let registration = &ctx.accounts.bundler_registration;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(
+            registration.withdraw_time > 0 && current_time >= registration.withdraw_time,
+            EntryPointError::WithdrawTimeNotReached
+        );
+        
+        let amount = registration.stake;
+        **registration.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.withdraw_to.try_borrow_mut_lamports()? += amount;
+        
+        emit!(BundlerStakeWithdrawn {
+            bundler: registration.bundler,
+            withdraw_address: ctx.accounts.withdraw_to.key(),
+            amount,
+        });
+        
+        Ok(())
+    }
+    
+    /// Set a bundler's status (authority only). A bundler can only be
+    /// throttled or banned while its success ratio is below
+    /// `min_bundler_success_bps`; it can always be restored to `Active`.
+    pub fn set_bundler_status(ctx: Context<SetBundlerStatus>, status: BundlerStatus) -> Result<()> {
+        let min_success_bps = ctx.accounts.entry_point.min_bundler_success_bps;
+        let registration = &mut ctx.accounts.bundler_registration;
+        
+        if status != BundlerStatus::Active {
+            require!(
+                registration.success_bps() < min_success_bps,
+                EntryPointError::BundlerAboveSuccessRatio
+            );
+        }
+        
+        let previous = registration.status;
+        registration.status = status;
+        
+        emit!(BundlerStatusChanged {
+            bundler: registration.bundler,
+            previous,
+            status,
+            success_bps: registration.success_bps(),
+        });
+        
+        Ok(())
+    }
+    
+    /// Update an entry point parameter (authority only). While
+    /// `timelock_delay` is set the change is queued instead, to be applied
+    /// by `execute_parameter_change` once the delay has passed.
+    pub fn update_parameter(
+        ctx: Context<UpdateEntryPoint>,
+        parameter: EntryPointParameter,
+    ) -> Result<()> {
+        parameter.validate()?;
+        let entry_point = &mut ctx.accounts.entry_point;
+        
+        if entry_point.timelock_delay == 0 {
+            apply_parameter(entry_point, parameter);
+            return Ok(());
+        }
+        
+        require!(entry_point.pending_change.is_none(), EntryPointError::ChangeAlreadyPending);
+        let execute_time = Clock::get()?.unix_timestamp
+            .checked_add(entry_point.timelock_delay)
+            .ok_or(EntryPointError::TimestampOverflow)?;
+        entry_point.pending_change = Some(PendingChange {
+            parameter,
+            execute_time,
+        });
+        
+        emit!(ParameterChangeQueued {
+            parameter,
+            execute_time,
+        });
+        
+        Ok(())
+    }
+    
+    /// Apply the queued parameter change once its timelock has passed
+    /// (authority only)
+    pub fn execute_parameter_change(ctx: Context<UpdateEntryPoint>) -> Result<()> {
+        let entry_point = &mut ctx.accounts.entry_point;
+        let pending = entry_point.pending_change
+            .ok_or(EntryPointError::NoPendingChange)?;
+        
+        require!(
+            Clock::get()?.unix_timestamp >= pending.execute_time,
+            EntryPointError::TimelockNotExpired
+        );
+        
+        entry_point.pending_change = None;
+        apply_parameter(entry_point, pending.parameter);
+        
+        Ok(())
+    }
+    
+    /// Drop the queued parameter change (authority only)
+    pub fn cancel_parameter_change(ctx: Context<UpdateEntryPoint>) -> Result<()> {
+        let entry_point = &mut ctx.accounts.entry_point;
+        let pending = entry_point.pending_change
+            .take()
+            .ok_or(EntryPointError::NoPendingChange)?;
+        
+        emit!(ParameterChangeCancelled {
+            parameter: pending.parameter,
+        });
+        
+        Ok(())
+    }
+    
+    /// Propose `new_authority` as the entry point's authority (authority
+    /// only). It takes over once it calls `accept_authority`, no earlier
+    /// than `timelock_delay` from now. Proposing the default key cancels a
+    /// pending transfer.
+    pub fn propose_authority(ctx: Context<UpdateEntryPoint>, new_authority: Pubkey) -> Result<()> {
+        let entry_point = &mut ctx.accounts.entry_point;
+        
+        entry_point.pending_authority = new_authority;
+        entry_point.authority_transfer_time = Clock::get()?.unix_timestamp
+            .checked_add(entry_point.timelock_delay)
+            .ok_or(EntryPointError::TimestampOverflow)?;
+        
+        emit!(AuthorityTransferProposed {
+            authority: entry_point.authority,
+            pending_authority: new_authority,
+            transfer_time: entry_point.authority_transfer_time,
+        });
+        
+        Ok(())
+    }
+    
+    /// Accept a proposed authority transfer, signed by the proposed
+    /// authority
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let entry_point = &mut ctx.accounts.entry_point;
+        
+        require!(
+            Clock::get()?.unix_timestamp >= entry_point.authority_transfer_time,
+            EntryPointError::TimelockNotExpired
+        );
+        
+        let previous = entry_point.authority;
+        entry_point.authority = entry_point.pending_authority;
+        entry_point.pending_authority = Pubkey::default();
+        entry_point.authority_transfer_time = 0;
+        
+        emit!(AuthorityTransferred {
+            previous,
+            authority: entry_point.authority,
+        });
+        
+        Ok(())
+    }
+    
+    /// Pause or unpause the entry point (authority only). Takes effect
+    /// immediately, bypassing the timelock. While paused, `handle_ops`,
+    /// `handle_aggregated_ops` and `simulate_handle_op` fail.
+    pub fn set_paused(ctx: Context<UpdateEntryPoint>, paused: bool) -> Result<()> {
+        let entry_point = &mut ctx.accounts.entry_point;
+        entry_point.paused = paused;
+        
+        emit!(PauseChanged {
+            authority: entry_point.authority,
+            paused,
+        });
+        
+        Ok(())
+    }
+    
+    /// Prefund gas for `entity`: a wallet, or a paymaster sponsoring
+    /// operations. Anyone can deposit for any entity.
+    pub fn deposit_to(
+        ctx: Context<DepositTo>,
+        entity: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: ctx.accounts.deposit.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        
+        let deposit = &mut ctx.accounts.deposit;
+        deposit.entity = entity;
+        deposit.bump = ctx.bumps["deposit"];
+        deposit.amount = deposit.amount
+            .checked_add(amount)
+            .ok_or(EntryPointError::GasCostOverflow)?;
+        
+        emit!(Deposited {
+            entity,
+            amount,
+            total_deposit: deposit.amount,
+        });
+        
+        Ok(())
+    }
+    
+    /// Withdraw from a deposit. Signed by the entity itself, or by a
+    /// paymaster's owner when the entity is that paymaster.
+    pub fn withdraw_to(ctx: Context<WithdrawTo>, amount: u64) -> Result<()> {
+        let deposit = &mut ctx.accounts.deposit;
+        let authority = ctx.accounts.authority.key();
+        
+        let is_paymaster_owner = ctx.accounts.paymaster
+            .as_ref()
+            .is_some_and(|paymaster| paymaster.owner == authority);
+        require!(
+            authority == deposit.entity || is_paymaster_owner,
+            EntryPointError::UnauthorizedWithdrawal
+        );
+        require!(amount <= deposit.amount, EntryPointError::InsufficientDeposit);
+        
+        deposit.amount -= amount;
+        **deposit.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.withdraw_to.try_borrow_mut_lamports()? += amount;
+        
+        emit!(DepositWithdrawn {
+            entity: deposit.entity,
+            withdraw_address: ctx.accounts.withdraw_to.key(),
+            amount,
+        });
+        
+        Ok(())
+    }
+    
+    /// Deposit balance of `entity`; zero if it never deposited
+    pub fn balance_of(ctx: Context<BalanceOf>, _entity: Pubkey) -> Result<u64> {
+        deposit_balance(&ctx.accounts.deposit)
+    }
+    
+    /// Get deposit and stake information for `entity`
     pub fn get_deposit_info(
         ctx: Context<GetDepositInfo>,
+        _entity: Pubkey,
     ) -> Result<DepositInfo> {
-        let account_info = &ctx.accounts.account_info;
+        let deposit = deposit_balance(&ctx.accounts.deposit)?;
         let stake_account = &ctx.accounts.stake_account;
         
         let deposit_info = match stake_account {
             Some(stake) => DepositInfo {
-                deposit: **account_info.lamports.borrow(),
+                deposit,
                 staked: stake.stake,
                 stake_delay: stake.unstake_delay,
                 withdraw_time: stake.withdraw_time,
             },
             None => DepositInfo {
-                deposit: **account_info.lamports.borrow(),
+                deposit,
                 staked: 0,
                 stake_delay: 0,
                 withdraw_time: 0,
@@ -203,8 +723,60 @@ pub struct EntryPoint {
     pub total_paymasters: u64,       // 8
     pub min_stake: u64,             // 8
     pub unstake_delay: i64,         // 8
+    pub min_bundler_stake: u64,     // 8
+    /// Success ratio, in basis points, below which bundlers may be
+    /// throttled or banned
+    pub min_bundler_success_bps: u16, // 2
+    pub require_registered_bundlers: bool, // 1
+    /// Delay before parameter changes and authority transfers take
+    /// effect; 0 applies parameter changes immediately
+    pub timelock_delay: i64,        // 8
+    pub pending_change: Option<PendingChange>, // 18
+    /// Proposed authority; the default key when none is pending
+    pub pending_authority: Pubkey,  // 32
+    /// When `pending_authority` can accept
+    pub authority_transfer_time: i64, // 8
+    /// Blocks operations from being handled
+    pub paused: bool,               // 1
+}
+
+/// A governed entry point parameter and its value
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum EntryPointParameter {
+    MinStake(u64),
+    UnstakeDelay(i64),
+    MinBundlerStake(u64),
+    MinBundlerSuccessBps(u16),
+    /// Only accept operations from registered bundlers
+    RequireRegisteredBundlers(bool),
+    TimelockDelay(i64),
 }
 
+impl EntryPointParameter {
+    fn validate(&self) -> Result<()> {
+        match *self {
+            EntryPointParameter::UnstakeDelay(delay) | EntryPointParameter::TimelockDelay(delay) => {
+                require!((0..=MAX_DELAY).contains(&delay), EntryPointError::InvalidParameter)
+            }
+            EntryPointParameter::MinBundlerSuccessBps(bps) => {
+                require!(bps <= 10_000, EntryPointError::InvalidSuccessRatio)
+            }
+            _ => {}
+        }
+        
+        Ok(())
+    }
+}
+
+/// A timelocked parameter change
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, InitSpace)]
+pub struct PendingChange {
+    pub parameter: EntryPointParameter,
+    pub execute_time: i64,
+}
+
+/// Stake locked by a paymaster's owner (the `paymaster` key), held as
+/// lamports in this account on top of its rent
 #[account]
 #[derive(InitSpace)]
 pub struct PaymasterStake {
@@ -214,23 +786,99 @@ pub struct PaymasterStake {
     pub withdraw_time: i64,         // 8
 }
 
+/// A registered bundler (the `bundler` key) and its record. The stake is
+/// held as lamports in this account on top of its rent.
+#[account]
+#[derive(InitSpace)]
+pub struct BundlerRegistration {
+    pub bundler: Pubkey,            // 32
+    pub stake: u64,                 // 8
+    /// When the stake can be withdrawn; 0 while registered
+    pub withdraw_time: i64,         // 8
+    pub status: BundlerStatus,      // 1
+    pub successful_operations: u64, // 8
+    /// Operations the bundler submitted that were rejected in validation.
+    /// An operation that passes validation but fails in execution aborts
+    /// its whole batch, so it is counted as neither success nor failure.
+    pub failed_operations: u64,     // 8
+}
+
+impl BundlerRegistration {
+    /// Share of submitted operations that succeeded, in basis points.
+    /// A bundler with no operations yet counts as fully successful.
+    pub fn success_bps(&self) -> u16 {
+        let total = self.successful_operations as u128 + self.failed_operations as u128;
+        if total == 0 {
+            return 10_000;
+        }
+        (self.successful_operations as u128 * 10_000 / total) as u16
+    }
+}
+
+/// Lamports an entity has prefunded gas with, at `deposit_address(entity)`.
+/// The account's balance is its rent plus `amount`.
+#[account]
+#[derive(InitSpace)]
+pub struct Deposit {
+    pub entity: Pubkey,             // 32
+    pub amount: u64,                // 8
+    pub bump: u8,                   // 1
+}
+
 // Data Structures
+/// Where one operation's accounts sit in `handle_ops`' remaining accounts.
+/// Each operation takes, in order:
+///
+/// 1. its sender wallet (writable)
+/// 2. the deposit of whoever pays its gas: the paymaster when sponsored,
+///    otherwise the sender (writable, see `deposit_address`)
+/// 3. when `paymaster_and_data` names a paymaster: the paymaster (writable),
+///    the account holding its payment data and the `PaymasterStake` of the
+///    paymaster's owner
+/// 4. `call_accounts` accounts, forwarded to the wallet for the calls
+/// 5. `paymaster_accounts` accounts, forwarded to the paymaster's
+///    `validate_paymaster_user_op` and `post_op`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct OpAccounts {
+    pub call_accounts: u8,
+    pub paymaster_accounts: u8,
+}
+
+/// Operations sharing a signature aggregator, for `handle_aggregated_ops`
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct UserOperation {
-    pub sender: Pubkey,
-    pub nonce: u64,
-    pub init_code: Vec<u8>,
-    pub call_data: Vec<u8>,
-    pub call_gas_limit: u64,
-    pub verification_gas_limit: u64,
-    pub pre_verification_gas: u64,
-    pub max_fee_per_gas: u64,
-    pub max_priority_fee_per_gas: u64,
-    pub paymaster_and_data: Vec<u8>,
-    pub signature: [u8; 64],
+pub struct UserOpsPerAggregator {
+    /// Program verifying the group's signatures
+    pub aggregator: Pubkey,
+    pub user_ops: Vec<UserOperation>,
+    pub op_accounts: Vec<OpAccounts>,
+    /// Signature over the whole group, passed to the aggregator
+    pub signature: Vec<u8>,
+    /// Accounts forwarded to the aggregator's `validate_signatures`
+    pub aggregator_accounts: u8,
 }
 
+/// One operation as passed to an aggregator's
+/// `validate_signatures(user_ops: Vec<AggregatedUserOp>, signature: Vec<u8>)`.
+/// The aggregator must fail unless `signature` proves every `signer` signed
+/// its `user_op_hash`.
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AggregatedUserOp {
+    pub user_op_hash: [u8; 32],
+    pub sender: Pubkey,
+    /// Owner of the sending wallet
+    pub signer: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum BundlerStatus {
+    Active,
+    /// May submit at most `THROTTLED_MAX_OPERATIONS` operations per call
+    Throttled,
+    /// May not submit operations
+    Banned,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ValidationResult {
     Valid,
     InvalidSignature,
@@ -238,6 +886,81 @@ pub enum ValidationResult {
     InsufficientFunds,
     PaymasterRejected,
     GasLimitExceeded,
+    /// `sender` does not match the wallet account passed for the operation
+    SenderMismatch,
+    WalletFrozen,
+    InvalidCallData,
+    /// The paymaster's owner has less than `min_stake` locked
+    PaymasterNotStaked,
+    /// The wallet would queue the operation for co-signing instead of
+    /// executing it; such operations must be submitted to the wallet directly
+    RequiresCoSigning,
+    /// The wallet's aggregator is not the one its operation was submitted
+    /// under, or the wallet has one and the operation was not aggregated
+    AggregatorMismatch,
+}
+
+impl From<UserOpStatus> for ValidationResult {
+    fn from(status: UserOpStatus) -> Self {
+        match status {
+            UserOpStatus::Valid => ValidationResult::Valid,
+            UserOpStatus::WrongSender => ValidationResult::SenderMismatch,
+            UserOpStatus::WalletFrozen => ValidationResult::WalletFrozen,
+            UserOpStatus::InvalidNonce => ValidationResult::InvalidNonce,
+            UserOpStatus::InvalidSignature => ValidationResult::InvalidSignature,
+            UserOpStatus::InvalidCallData => ValidationResult::InvalidCallData,
+            UserOpStatus::RequiresCoSigning => ValidationResult::RequiresCoSigning,
+        }
+    }
+}
+
+/// Stake an entity has locked with the entry point
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct StakeInfo {
+    pub entity: Pubkey,
+    /// Zero when unstaked or unlocking
+    pub stake: u64,
+    pub unstake_delay: i64,
+}
+
+/// What a valid operation needs, as reported by `simulate_validation`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ReturnInfo {
+    /// Compute units used by validation, plus `pre_verification_gas`
+    pub pre_op_gas: u64,
+    /// Lamports the payer's deposit must hold
+    pub prefund: u64,
+    pub valid_after: u64,
+    pub valid_until: u64,
+    /// Context the paymaster returned for `post_op`
+    pub paymaster_context: Vec<u8>,
+}
+
+/// Return data of `simulate_validation`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ValidationSimulation {
+    pub user_op_hash: [u8; 32],
+    pub validation_result: ValidationResult,
+    /// Set when `validation_result` is `Valid`
+    pub return_info: Option<ReturnInfo>,
+    pub sender_info: StakeInfo,
+    /// Set when `init_code` names a factory
+    pub factory_info: Option<StakeInfo>,
+    /// Stake of the paymaster's owner, when sponsored
+    pub paymaster_info: Option<StakeInfo>,
+    /// Aggregator the wallet requires, if any
+    pub aggregator: Option<Pubkey>,
+}
+
+/// Return data of `simulate_handle_op`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ExecutionSimulation {
+    pub user_op_hash: [u8; 32],
+    pub validation_result: ValidationResult,
+    /// Whether the operation passed validation and its calls succeeded
+    pub executed: bool,
+    pub gas_used: u64,
+    pub actual_gas_cost: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -268,21 +991,66 @@ pub struct Initialize<'info> {
 
 #[derive(Accounts)]
 pub struct HandleOps<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"entry_point"],
+        bump,
+        constraint = !entry_point.paused @ EntryPointError::EntryPointPaused
+    )]
     pub entry_point: Account<'info, EntryPoint>,
     
     pub bundler: Signer<'info>,
+    
+    /// CHECK: The bundler's registration PDA, passed whether or not the
+    /// bundler is registered so that its status always applies
+    #[account(
+        mut,
+        seeds = [b"bundler", bundler.key().as_ref()],
+        bump
+    )]
+    pub bundler_registration: UncheckedAccount<'info>,
+    
+    pub wallet_program: Program<'info, NexusWallet>,
+    
+    /// Required when any operation is sponsored by a paymaster
+    pub paymaster_program: Option<Program<'info, NexusPaymaster>>,
+    
+    /// CHECK: Receives the gas payments of the batch
+    #[account(mut)]
+    pub beneficiary: UncheckedAccount<'info>,
+    
+    /// CHECK: Instructions sysvar, holding the owners' ed25519 signatures
+    /// that wallets verify
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
 pub struct SimulateValidation<'info> {
     pub entry_point: Account<'info, EntryPoint>,
+    
+    pub wallet_program: Program<'info, NexusWallet>,
+    
+    /// Required when the operation is sponsored by a paymaster
+    pub paymaster_program: Option<Program<'info, NexusPaymaster>>,
+    
+    /// CHECK: `PaymasterStake` PDA of the sender, if it has one; read by
+    /// `stake_info`
+    pub sender_stake: Option<UncheckedAccount<'info>>,
+    
+    /// CHECK: `PaymasterStake` PDA of the factory named by `init_code`, if
+    /// it has one; read by `stake_info`
+    pub factory_stake: Option<UncheckedAccount<'info>>,
+    
+    /// CHECK: Instructions sysvar, holding the owner's ed25519 signature
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
 pub struct AddStake<'info> {
     #[account(
-        init,
+        init_if_needed,
         payer = paymaster,
         space = 8 + PaymasterStake::INIT_SPACE,
         seeds = [b"paymaster_stake", paymaster.key().as_ref()],
@@ -290,14 +1058,12 @@ pub struct AddStake<'info> {
     )]
     pub paymaster_stake: Account<'info, PaymasterStake>,
     
+    #[account(mut)]
     pub entry_point: Account<'info, EntryPoint>,
     
     #[account(mut)]
     pub paymaster: Signer<'info>,
     
-    #[account(mut)]
-    pub deposit: Signer<'info>,
-    
     pub system_program: Program<'info, System>,
 }
 
@@ -325,6 +1091,7 @@ pub struct WithdrawStake<'info> {
     )]
     pub paymaster_stake: Account<'info, PaymasterStake>,
     
+    #[account(mut)]
     pub paymaster: Signer<'info>,
     
     #[account(mut)]
@@ -333,9 +1100,182 @@ pub struct WithdrawStake<'info> {
 }
 
 #[derive(Accounts)]
+pub struct SlashStake<'info> {
+    #[account(
+        seeds = [b"entry_point"],
+        bump,
+        has_one = authority
+    )]
+    pub entry_point: Account<'info, EntryPoint>,
+    
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"paymaster_stake", paymaster_stake.paymaster.as_ref()],
+        bump
+    )]
+    pub paymaster_stake: Account<'info, PaymasterStake>,
+    
+    #[account(mut)]
+    /// CHECK: This account will receive the slashed stake
+    pub recipient: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterBundler<'info> {
+    #[account(
+        init,
+        payer = bundler,
+        space = 8 + BundlerRegistration::INIT_SPACE,
+        seeds = [b"bundler", bundler.key().as_ref()],
+        bump
+    )]
+    pub bundler_registration: Account<'info, BundlerRegistration>,
+    
+    pub entry_point: Account<'info, EntryPoint>,
+    
+    #[account(mut)]
+    pub bundler: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnregisterBundler<'info> {
+    #[account(
+        mut,
+        seeds = [b"bundler", bundler.key().as_ref()],
+        bump,
+        has_one = bundler
+    )]
+    pub bundler_registration: Account<'info, BundlerRegistration>,
+    
+    pub entry_point: Account<'info, EntryPoint>,
+    
+    pub bundler: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawBundlerStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"bundler", bundler.key().as_ref()],
+        bump,
+        has_one = bundler,
+        close = bundler
+    )]
+    pub bundler_registration: Account<'info, BundlerRegistration>,
+    
+    #[account(mut)]
+    pub bundler: Signer<'info>,
+    
+    #[account(mut)]
+    /// CHECK: This account will receive the withdrawn stake
+    pub withdraw_to: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetBundlerStatus<'info> {
+    #[account(
+        seeds = [b"entry_point"],
+        bump,
+        has_one = authority
+    )]
+    pub entry_point: Account<'info, EntryPoint>,
+    
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"bundler", bundler_registration.bundler.as_ref()],
+        bump
+    )]
+    pub bundler_registration: Account<'info, BundlerRegistration>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateEntryPoint<'info> {
+    #[account(
+        mut,
+        seeds = [b"entry_point"],
+        bump,
+        has_one = authority
+    )]
+    pub entry_point: Account<'info, EntryPoint>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"entry_point"],
+        bump,
+        constraint = entry_point.pending_authority != Pubkey::default()
+            @ EntryPointError::NoPendingAuthority,
+        constraint = entry_point.pending_authority == pending_authority.key()
+            @ EntryPointError::NotPendingAuthority
+    )]
+    pub entry_point: Account<'info, EntryPoint>,
+    
+    pub pending_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(entity: Pubkey)]
+pub struct DepositTo<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Deposit::INIT_SPACE,
+        seeds = [b"deposit", entity.as_ref()],
+        bump
+    )]
+    pub deposit: Account<'info, Deposit>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTo<'info> {
+    #[account(
+        mut,
+        seeds = [b"deposit", deposit.entity.as_ref()],
+        bump = deposit.bump
+    )]
+    pub deposit: Account<'info, Deposit>,
+    
+    /// The entity, or the owner of the paymaster that is the entity
+    pub authority: Signer<'info>,
+    
+    /// Required when a paymaster's owner withdraws its deposit
+    #[account(address = deposit.entity)]
+    pub paymaster: Option<Account<'info, Paymaster>>,
+    
+    #[account(mut)]
+    /// CHECK: This account will receive the withdrawn deposit
+    pub withdraw_to: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(entity: Pubkey)]
+pub struct BalanceOf<'info> {
+    /// CHECK: Deposit PDA of `entity`, which may not exist yet
+    #[account(seeds = [b"deposit", entity.as_ref()], bump)]
+    pub deposit: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(entity: Pubkey)]
 pub struct GetDepositInfo<'info> {
-    /// CHECK: Account to get deposit info for
-    pub account_info: AccountInfo<'info>,
+    /// CHECK: Deposit PDA of `entity`, which may not exist yet
+    #[account(seeds = [b"deposit", entity.as_ref()], bump)]
+    pub deposit: UncheckedAccount<'info>,
     
     pub stake_account: Option<Account<'info, PaymasterStake>>,
 }
@@ -358,12 +1298,28 @@ pub struct UserOperationEvent {
     pub actual_gas_used: u64,
 }
 
+#[event]
+pub struct UserOperationRejected {
+    pub user_op_hash: [u8; 32],
+    pub sender: Pubkey,
+    pub nonce: u64,
+    pub validation_result: ValidationResult,
+}
+
 #[event]
 pub struct BatchProcessed {
     pub beneficiary: Pubkey,
     pub total_operations: u8,
     pub successful_operations: u8,
     pub total_gas_used: u64,
+    /// Lamports paid to `beneficiary`
+    pub total_gas_cost: u64,
+}
+
+#[event]
+pub struct SignaturesAggregated {
+    pub aggregator: Pubkey,
+    pub operations: u8,
 }
 
 #[event]
@@ -373,6 +1329,20 @@ pub struct ValidationSimulated {
     pub validation_result: ValidationResult,
 }
 
+#[event]
+pub struct Deposited {
+    pub entity: Pubkey,
+    pub amount: u64,
+    pub total_deposit: u64,
+}
+
+#[event]
+pub struct DepositWithdrawn {
+    pub entity: Pubkey,
+    pub withdraw_address: Pubkey,
+    pub amount: u64,
+}
+
 #[event]
 pub struct StakeAdded {
     pub paymaster: Pubkey,
@@ -393,6 +1363,77 @@ pub struct StakeWithdrawn {
     pub amount: u64,
 }
 
+#[event]
+pub struct StakeSlashed {
+    pub paymaster: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub remaining_stake: u64,
+}
+
+#[event]
+pub struct BundlerRegistered {
+    pub bundler: Pubkey,
+    pub stake: u64,
+}
+
+#[event]
+pub struct BundlerUnregistered {
+    pub bundler: Pubkey,
+    pub withdraw_time: i64,
+}
+
+#[event]
+pub struct BundlerStakeWithdrawn {
+    pub bundler: Pubkey,
+    pub withdraw_address: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BundlerStatusChanged {
+    pub bundler: Pubkey,
+    pub previous: BundlerStatus,
+    pub status: BundlerStatus,
+    pub success_bps: u16,
+}
+
+#[event]
+pub struct ParameterUpdated {
+    pub previous: EntryPointParameter,
+    pub parameter: EntryPointParameter,
+}
+
+#[event]
+pub struct ParameterChangeQueued {
+    pub parameter: EntryPointParameter,
+    pub execute_time: i64,
+}
+
+#[event]
+pub struct ParameterChangeCancelled {
+    pub parameter: EntryPointParameter,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub transfer_time: i64,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct PauseChanged {
+    pub authority: Pubkey,
+    pub paused: bool,
+}
+
 // Error Definitions
 #[error_code]
 pub enum EntryPointError {
@@ -410,29 +1451,612 @@ pub enum EntryPointError {
     InvalidSignature,
     #[msg("Invalid nonce")]
     InvalidNonce,
+    #[msg("Operation accounts do not match the operations")]
+    InvalidOpAccounts,
+    #[msg("Paymaster accounts or program missing")]
+    MissingPaymasterAccounts,
+    #[msg("Gas cost overflow")]
+    GasCostOverflow,
+    #[msg("Timestamp overflow")]
+    TimestampOverflow,
+    #[msg("Only the entity or its paymaster's owner can withdraw")]
+    UnauthorizedWithdrawal,
+    #[msg("Insufficient deposit")]
+    InsufficientDeposit,
+    #[msg("Stake below the entry point minimum")]
+    InsufficientStake,
+    #[msg("Stake overflow")]
+    StakeOverflow,
+    #[msg("No stake to unlock")]
+    NotStaked,
+    #[msg("Stake is already unlocking")]
+    StakeUnlocking,
+    #[msg("Aggregator account does not match the group's aggregator program")]
+    InvalidAggregator,
+    #[msg("Bundler is not registered")]
+    BundlerNotRegistered,
+    #[msg("Bundler is banned")]
+    BundlerBanned,
+    #[msg("Throttled bundler submitted too many operations")]
+    BundlerThrottled,
+    #[msg("Bundler's success ratio is not below the minimum")]
+    BundlerAboveSuccessRatio,
+    #[msg("Success ratio must be at most 10000 basis points")]
+    InvalidSuccessRatio,
+    #[msg("Simulation complete; the result is in the return data")]
+    SimulationComplete,
+    #[msg("Invalid parameter value")]
+    InvalidParameter,
+    #[msg("A parameter change is already pending")]
+    ChangeAlreadyPending,
+    #[msg("No parameter change is pending")]
+    NoPendingChange,
+    #[msg("Timelock has not expired")]
+    TimelockNotExpired,
+    #[msg("No authority transfer is pending")]
+    NoPendingAuthority,
+    #[msg("Signer is not the pending authority")]
+    NotPendingAuthority,
+    #[msg("Entry point is paused")]
+    EntryPointPaused,
 }
 
 // Helper Functions
-fn calculate_user_op_hash(user_op: &UserOperation) -> Result<[u8; 32]> {
-    let hash = anchor_lang::solana_program::hash::hashv(&[
-        user_op.sender.as_ref(),
-        &user_op.nonce.to_le_bytes(),
-        &user_op.call_data,
-        &user_op.call_gas_limit.to_le_bytes(),
-        &user_op.max_fee_per_gas.to_le_bytes(),
-    ]);
-    Ok(hash.to_bytes())
+/// One operation's slice of `handle_ops`' remaining accounts
+struct OpAccountInfos<'a, 'info> {
+    wallet: &'a AccountInfo<'info>,
+    deposit: &'a AccountInfo<'info>,
+    /// The paymaster, its payment data account and its owner's stake
+    paymaster: Option<(&'a AccountInfo<'info>, &'a AccountInfo<'info>, &'a AccountInfo<'info>)>,
+    calls: &'a [AccountInfo<'info>],
+    paymaster_accounts: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> OpAccountInfos<'a, 'info> {
+    /// Take `user_op`'s accounts off the front of `accounts`, returning them
+    /// and the accounts of the following operations
+    fn split(
+        accounts: &'a [AccountInfo<'info>],
+        user_op: &UserOperation,
+        layout: &OpAccounts,
+    ) -> Result<(Self, &'a [AccountInfo<'info>])> {
+        let sponsored = user_op.paymaster()?.is_some();
+        let len = 2
+            + if sponsored { 3 } else { 0 }
+            + layout.call_accounts as usize
+            + layout.paymaster_accounts as usize;
+        require!(accounts.len() >= len, EntryPointError::InvalidOpAccounts);
+        
+        let (op_accounts, rest) = accounts.split_at(len);
+        let (paymaster, forwarded) = if sponsored {
+            (
+                Some((&op_accounts[2], &op_accounts[3], &op_accounts[4])),
+                &op_accounts[5..],
+            )
+        } else {
+            (None, &op_accounts[2..])
+        };
+        let (calls, paymaster_accounts) = forwarded.split_at(layout.call_accounts as usize);
+        
+        Ok((
+            Self {
+                wallet: &op_accounts[0],
+                deposit: &op_accounts[1],
+                paymaster,
+                calls,
+                paymaster_accounts,
+            },
+            rest,
+        ))
+    }
+    
+    /// `split` for each of `user_ops` in turn
+    fn split_all(
+        mut accounts: &'a [AccountInfo<'info>],
+        user_ops: &[UserOperation],
+        layouts: &[OpAccounts],
+    ) -> Result<(Vec<Self>, &'a [AccountInfo<'info>])> {
+        require!(layouts.len() == user_ops.len(), EntryPointError::InvalidOpAccounts);
+        
+        let mut ops = Vec::with_capacity(user_ops.len());
+        for (user_op, layout) in user_ops.iter().zip(layouts.iter()) {
+            let (op, rest) = Self::split(accounts, user_op, layout)?;
+            ops.push(op);
+            accounts = rest;
+        }
+        
+        Ok((ops, accounts))
+    }
+}
+
+/// Operations a throttled bundler may submit per call
+pub const THROTTLED_MAX_OPERATIONS: usize = 4;
+
+/// Longest unstake or timelock delay, 30 days
+pub const MAX_DELAY: i64 = 30 * 24 * 60 * 60;
+
+/// The bundler's registration, if its PDA holds one
+fn load_bundler_registration<'info>(
+    accounts: &HandleOps<'info>,
+) -> Result<Option<Account<'info, BundlerRegistration>>> {
+    let info = accounts.bundler_registration.to_account_info();
+    if info.owner != &crate::ID {
+        return Ok(None);
+    }
+    Account::try_from(&info).map(Some)
+}
+
+/// Check the bundler may submit `operations` operations: it must be
+/// registered if the entry point requires it, and a registration must be
+/// active, or throttled and within `THROTTLED_MAX_OPERATIONS`
+fn check_bundler(accounts: &HandleOps, operations: usize) -> Result<()> {
+    let Some(registration) = load_bundler_registration(accounts)? else {
+        require!(
+            !accounts.entry_point.require_registered_bundlers,
+            EntryPointError::BundlerNotRegistered
+        );
+        return Ok(());
+    };
+    
+    require!(registration.withdraw_time == 0, EntryPointError::BundlerNotRegistered);
+    match registration.status {
+        BundlerStatus::Active => {}
+        BundlerStatus::Throttled => require!(
+            operations <= THROTTLED_MAX_OPERATIONS,
+            EntryPointError::BundlerThrottled
+        ),
+        BundlerStatus::Banned => return err!(EntryPointError::BundlerBanned),
+    }
+    
+    Ok(())
+}
+
+/// Running totals of a `handle_ops` or `handle_aggregated_ops` call
+#[derive(Default)]
+struct BatchTotals {
+    operations: u64,
+    successful_operations: u8,
+    gas_used: u64,
+    gas_cost: u64,
+}
+
+/// Process `user_ops`, whose accounts are `ops`, emitting an event for each
+/// and adding them to `totals`. `aggregator` is the aggregator that has
+/// already verified their signatures, if any.
+fn process_user_operations<'info>(
+    accounts: &HandleOps<'info>,
+    user_ops: &[UserOperation],
+    ops: &[OpAccountInfos<'_, 'info>],
+    aggregator: Option<Pubkey>,
+    entry_point_bump: u8,
+    totals: &mut BatchTotals,
+) -> Result<()> {
+    for (user_op, op_accounts) in user_ops.iter().zip(ops.iter()) {
+        let user_op_hash = user_op.canonical_hash();
+        let outcome = process_user_operation(
+            accounts,
+            op_accounts,
+            user_op,
+            &user_op_hash,
+            aggregator,
+            entry_point_bump,
+        )?;
+        totals.operations += 1;
+        
+        match outcome {
+            Ok(charge) => {
+                totals.successful_operations += 1;
+                totals.gas_used += charge.gas_used;
+                totals.gas_cost += charge.cost;
+                
+                emit!(UserOperationEvent {
+                    user_op_hash,
+                    sender: user_op.sender,
+                    paymaster: user_op.paymaster()?,
+                    nonce: user_op.nonce,
+                    success: true,
+                    actual_gas_cost: charge.cost,
+                    actual_gas_used: charge.gas_used,
+                });
+            }
+            Err(validation_result) => {
+                emit!(UserOperationRejected {
+                    user_op_hash,
+                    sender: user_op.sender,
+                    nonce: user_op.nonce,
+                    validation_result,
+                });
+                
+                emit!(UserOperationEvent {
+                    user_op_hash,
+                    sender: user_op.sender,
+                    paymaster: user_op.paymaster()?,
+                    nonce: user_op.nonce,
+                    success: false,
+                    actual_gas_cost: 0,
+                    actual_gas_used: 0,
+                });
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Record a processed batch and emit its summary
+fn finish_batch(accounts: &mut HandleOps, totals: &BatchTotals) -> Result<()> {
+    let entry_point = &mut accounts.entry_point;
+    entry_point.total_operations += totals.operations;
+    
+    if let Some(mut registration) = load_bundler_registration(accounts)? {
+        let successful = totals.successful_operations as u64;
+        registration.successful_operations += successful;
+        registration.failed_operations += totals.operations - successful;
+        registration.exit(&crate::ID)?;
+    }
+    
+    emit!(BatchProcessed {
+        beneficiary: accounts.beneficiary.key(),
+        total_operations: totals.operations as u8,
+        successful_operations: totals.successful_operations,
+        total_gas_used: totals.gas_used,
+        total_gas_cost: totals.gas_cost,
+    });
+    
+    Ok(())
+}
+
+/// Have `aggregator` verify `signature` for `user_ops` through its Anchor
+/// `validate_signatures` instruction, which fails the call if invalid
+fn validate_signatures<'info>(
+    aggregator: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    user_ops: Vec<AggregatedUserOp>,
+    signature: &[u8],
+) -> Result<()> {
+    let mut data = hash(b"global:validate_signatures").to_bytes()[..8].to_vec();
+    user_ops.serialize(&mut data)?;
+    signature.to_vec().serialize(&mut data)?;
+    
+    let instruction = Instruction {
+        program_id: aggregator.key(),
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    invoke(&instruction, accounts)?;
+    
+    Ok(())
 }
 
-fn process_user_operation(user_op: &UserOperation, _bundler: Pubkey) -> Result<u64> {
-    // Validate user operation
-    validate_user_operation(user_op)?;
+/// What validating an operation established, for executing it
+struct OpValidation<'a, 'info> {
+    /// Compute units remaining when validation started
+    validation_start: u64,
+    deposit: Account<'info, Deposit>,
+    /// The paymaster program, the paymaster and its `post_op` context
+    paymaster: Option<(&'a AccountInfo<'info>, &'a AccountInfo<'info>, Vec<u8>)>,
+    valid_after: u64,
+    valid_until: u64,
+    aggregator: Option<Pubkey>,
+}
+
+/// Validate one operation: its gas fields, its payer's deposit, its
+/// wallet and, when sponsored, its paymaster. Returns why it was rejected
+/// if it was.
+fn validate_operation<'a, 'info>(
+    entry_point: &EntryPoint,
+    wallet_program: &'a AccountInfo<'info>,
+    paymaster_program: Option<&'a AccountInfo<'info>>,
+    instructions_sysvar: &AccountInfo<'info>,
+    op_accounts: &OpAccountInfos<'a, 'info>,
+    user_op: &UserOperation,
+    user_op_hash: &[u8; 32],
+) -> Result<std::result::Result<OpValidation<'a, 'info>, ValidationResult>> {
+    let validation_start = remaining_compute_units();
+    
+    let validation_result = validate_user_operation(user_op)?;
+    if validation_result != ValidationResult::Valid {
+        return Ok(Err(validation_result));
+    }
+    
+    // Gas is prefunded from the paymaster's deposit when sponsored,
+    // otherwise from the sender's
+    let payer = user_op.paymaster()?.unwrap_or(user_op.sender);
+    if op_accounts.deposit.owner != &crate::ID {
+        return Ok(Err(ValidationResult::InsufficientFunds));
+    }
+    let deposit = Account::<Deposit>::try_from(op_accounts.deposit)?;
+    if deposit.entity != payer || deposit.amount < user_op.required_prefund()? {
+        return Ok(Err(ValidationResult::InsufficientFunds));
+    }
+    
+    // Account validation: signature, nonce and wallet state
+    let wallet_validation = nexus_wallet::cpi::validate_user_op(
+        CpiContext::new(
+            wallet_program.clone(),
+            nexus_wallet::cpi::accounts::ValidateUserOp {
+                wallet: op_accounts.wallet.clone(),
+                instructions_sysvar: instructions_sysvar.clone(),
+            },
+        ),
+        user_op.clone(),
+    )?
+    .get();
+    let validation_result = ValidationResult::from(wallet_validation.status);
+    if validation_result != ValidationResult::Valid {
+        return Ok(Err(validation_result));
+    }
+    
+    // Paymaster validation
+    let (paymaster, valid_after, valid_until) = match op_accounts.paymaster {
+        Some((paymaster, paymaster_data, paymaster_stake)) => {
+            let paymaster_program = paymaster_program
+                .ok_or(EntryPointError::MissingPaymasterAccounts)?;
+            require!(
+                user_op.paymaster()? == Some(paymaster.key()),
+                EntryPointError::MissingPaymasterAccounts
+            );
+            
+            let owner = Account::<Paymaster>::try_from(paymaster)?.owner;
+            if !is_staked(paymaster_stake, &owner, entry_point.min_stake)? {
+                return Ok(Err(ValidationResult::PaymasterNotStaked));
+            }
+            
+            let validation = nexus_paymaster::cpi::validate_paymaster_user_op(
+                CpiContext::new(
+                    paymaster_program.clone(),
+                    nexus_paymaster::cpi::accounts::ValidatePaymasterUserOp {
+                        paymaster: paymaster.clone(),
+                        user_account: op_accounts.wallet.clone(),
+                        paymaster_data: paymaster_data.clone(),
+                    },
+                )
+                .with_remaining_accounts(op_accounts.paymaster_accounts.to_vec()),
+                *user_op_hash,
+                user_op.required_prefund()?,
+            )?
+            .get();
+            
+            let now = Clock::get()?.unix_timestamp.max(0) as u64;
+            if now < validation.valid_after || now > validation.valid_until {
+                return Ok(Err(ValidationResult::PaymasterRejected));
+            }
+            
+            (
+                Some((paymaster_program, paymaster, validation.context)),
+                validation.valid_after,
+                validation.valid_until,
+            )
+        }
+        None => (None, 0, u64::MAX),
+    };
+    
+    Ok(Ok(OpValidation {
+        validation_start,
+        deposit,
+        paymaster,
+        valid_after,
+        valid_until,
+        aggregator: wallet_validation.aggregator,
+    }))
+}
+
+/// Validate, execute and pay for one operation. Returns what it was
+/// charged, or why it was rejected before executing.
+fn process_user_operation<'info>(
+    accounts: &HandleOps<'info>,
+    op_accounts: &OpAccountInfos<'_, 'info>,
+    user_op: &UserOperation,
+    user_op_hash: &[u8; 32],
+    aggregator: Option<Pubkey>,
+    entry_point_bump: u8,
+) -> Result<std::result::Result<GasCharge, ValidationResult>> {
+    let validation = match validate_operation(
+        &accounts.entry_point,
+        &accounts.wallet_program,
+        accounts.paymaster_program.as_deref(),
+        &accounts.instructions_sysvar,
+        op_accounts,
+        user_op,
+        user_op_hash,
+    )? {
+        Ok(validation) => validation,
+        Err(validation_result) => return Ok(Err(validation_result)),
+    };
+    if validation.aggregator != aggregator {
+        return Ok(Err(ValidationResult::AggregatorMismatch));
+    }
+    let mut deposit = validation.deposit;
+    
+    // Execution. The wallet re-checks the signature, unless the aggregator
+    // has verified it, and consumes the nonce.
+    let execution_start = remaining_compute_units();
+    if let Some(aggregator) = aggregator {
+        let signer_seeds: &[&[u8]] = &[b"entry_point", &[entry_point_bump]];
+        nexus_wallet::cpi::execute_aggregated_user_operation(
+            CpiContext::new_with_signer(
+                accounts.wallet_program.to_account_info(),
+                nexus_wallet::cpi::accounts::ExecuteAggregatedUserOperation {
+                    wallet: op_accounts.wallet.clone(),
+                    entry_point: accounts.entry_point.to_account_info(),
+                },
+                &[signer_seeds],
+            )
+            .with_remaining_accounts(op_accounts.calls.to_vec()),
+            user_op.clone(),
+            aggregator,
+            *user_op_hash,
+        )?;
+    } else {
+        nexus_wallet::cpi::execute_user_operation(
+            CpiContext::new(
+                accounts.wallet_program.to_account_info(),
+                nexus_wallet::cpi::accounts::ExecuteUserOperation {
+                    wallet: op_accounts.wallet.clone(),
+                    pending_operation: None,
+                    payer: None,
+                    system_program: None,
+                    paymaster: None,
+                    paymaster_data_account: None,
+                    paymaster_program: None,
+                    instructions_sysvar: accounts.instructions_sysvar.to_account_info(),
+                },
+            )
+            .with_remaining_accounts(op_accounts.calls.to_vec()),
+            user_op.clone(),
+            None,
+        )?;
+    }
+    
+    let charge = GasCharge::measure(
+        user_op,
+        validation.validation_start,
+        execution_start,
+        remaining_compute_units(),
+    )?;
+    
+    // Reaching this point means every call succeeded
+    if let Some((paymaster_program, paymaster, context)) = validation.paymaster {
+        nexus_paymaster::cpi::post_op(
+            CpiContext::new(
+                paymaster_program.clone(),
+                nexus_paymaster::cpi::accounts::PostOp {
+                    paymaster: paymaster.clone(),
+                },
+            )
+            .with_remaining_accounts(op_accounts.paymaster_accounts.to_vec()),
+            PostOpMode::OpSucceeded,
+            context,
+            charge.cost,
+        )?;
+    }
     
-    // Execute the operation
-    // This would involve calling the target program with the user operation data
+    deposit.amount = deposit.amount
+        .checked_sub(charge.cost)
+        .ok_or(EntryPointError::InsufficientDeposit)?;
+    deposit.exit(&crate::ID)?;
+    **op_accounts.deposit.try_borrow_mut_lamports()? -= charge.cost;
+    **accounts.beneficiary.try_borrow_mut_lamports()? += charge.cost;
     
-    // Return gas used (simplified)
-    Ok(user_op.call_gas_limit)
+    Ok(Ok(charge))
+}
+
+/// What an executed operation is charged. One unit of gas is one compute
+/// unit.
+struct GasCharge {
+    gas_used: u64,
+    cost: u64,
+}
+
+impl GasCharge {
+    /// Charge for an operation from the compute units remaining when its
+    /// validation started, when its execution started and after it. Each
+    /// phase is capped at its gas limit and `pre_verification_gas` is added
+    /// for the bundler's own overhead.
+    fn measure(
+        user_op: &UserOperation,
+        validation_start: u64,
+        execution_start: u64,
+        execution_end: u64,
+    ) -> Result<Self> {
+        let verification_gas = validation_start
+            .saturating_sub(execution_start)
+            .min(user_op.verification_gas_limit);
+        let call_gas = execution_start
+            .saturating_sub(execution_end)
+            .min(user_op.call_gas_limit);
+        
+        let gas_used = verification_gas
+            .checked_add(call_gas)
+            .and_then(|gas| gas.checked_add(user_op.pre_verification_gas))
+            .ok_or(EntryPointError::GasCostOverflow)?;
+        let cost = gas_used
+            .checked_mul(user_op.gas_price())
+            .ok_or(EntryPointError::GasCostOverflow)?;
+        
+        Ok(Self { gas_used, cost })
+    }
+}
+
+/// Entry point PDA holding the lamports `entity` prefunds gas with
+pub fn deposit_address(entity: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"deposit", entity.as_ref()], &crate::ID)
+}
+
+/// Whether `stake` is a `PaymasterStake` of `paymaster` holding at least
+/// `min_stake` that is not unlocking
+fn is_staked(stake: &AccountInfo, paymaster: &Pubkey, min_stake: u64) -> Result<bool> {
+    if stake.owner != &crate::ID {
+        return Ok(false);
+    }
+    
+    let stake = Account::<PaymasterStake>::try_from(stake)?;
+    Ok(stake.paymaster == *paymaster && stake.withdraw_time == 0 && stake.stake >= min_stake)
+}
+
+/// Set `parameter` on `entry_point`, emitting its previous and new value
+fn apply_parameter(entry_point: &mut EntryPoint, parameter: EntryPointParameter) {
+    let previous = match parameter {
+        EntryPointParameter::MinStake(value) => {
+            EntryPointParameter::MinStake(std::mem::replace(&mut entry_point.min_stake, value))
+        }
+        EntryPointParameter::UnstakeDelay(value) => {
+            EntryPointParameter::UnstakeDelay(std::mem::replace(&mut entry_point.unstake_delay, value))
+        }
+        EntryPointParameter::MinBundlerStake(value) => {
+            EntryPointParameter::MinBundlerStake(std::mem::replace(&mut entry_point.min_bundler_stake, value))
+        }
+        EntryPointParameter::MinBundlerSuccessBps(value) => EntryPointParameter::MinBundlerSuccessBps(
+            std::mem::replace(&mut entry_point.min_bundler_success_bps, value),
+        ),
+        EntryPointParameter::RequireRegisteredBundlers(value) => EntryPointParameter::RequireRegisteredBundlers(
+            std::mem::replace(&mut entry_point.require_registered_bundlers, value),
+        ),
+        EntryPointParameter::TimelockDelay(value) => {
+            EntryPointParameter::TimelockDelay(std::mem::replace(&mut entry_point.timelock_delay, value))
+        }
+    };
+    
+    emit!(ParameterUpdated {
+        previous,
+        parameter,
+    });
+}
+
+/// `entity`'s stake, read from `stake` if it is the entity's
+/// `PaymasterStake`. Unlocking stake counts as none.
+fn stake_info(stake: Option<&AccountInfo>, entity: Pubkey) -> Result<StakeInfo> {
+    let mut info = StakeInfo {
+        entity,
+        stake: 0,
+        unstake_delay: 0,
+    };
+    
+    if let Some(stake) = stake.filter(|stake| stake.owner == &crate::ID) {
+        let stake = Account::<PaymasterStake>::try_from(stake)?;
+        if stake.paymaster == entity && stake.withdraw_time == 0 {
+            info.stake = stake.stake;
+            info.unstake_delay = stake.unstake_delay;
+        }
+    }
+    
+    Ok(info)
+}
+
+/// `amount` of a `Deposit` PDA, or zero when it has not been created
+fn deposit_balance(deposit: &AccountInfo) -> Result<u64> {
+    if deposit.owner != &crate::ID {
+        return Ok(0);
+    }
+    
+    Ok(Account::<Deposit>::try_from(deposit)?.amount)
 }
 
 fn validate_user_operation(user_op: &UserOperation) -> Result<ValidationResult> {
@@ -449,20 +2073,72 @@ fn validate_user_operation(user_op: &UserOperation) -> Result<ValidationResult>
     Ok(ValidationResult::Valid)
 }
 
-fn extract_paymaster(paymaster_and_data: &[u8]) -> Option<Pubkey> {
-    if paymaster_and_data.len() >= 32 {
-        let mut pubkey_bytes = [0u8; 32];
-        pubkey_bytes.copy_from_slice(&paymaster_and_data[..32]);
-        Some(Pubkey::from(pubkey_bytes))
-    } else {
-        None
-    }
-}
-
 impl EntryPoint {
-    pub const INIT_SPACE: usize = 32 + 8 + 8 + 8 + 8;
+    pub const INIT_SPACE: usize = 32 + 8 + 8 + 8 + 8 + 8 + 2 + 1 + 8 + (1 + 9 + 8) + 32 + 8 + 1;
 }
 
 impl PaymasterStake {
     pub const INIT_SPACE: usize = 32 + 8 + 8 + 8 + 1;
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn user_op(max_fee_per_gas: u64, max_priority_fee_per_gas: u64) -> UserOperation {
+        UserOperation {
+            sender: Pubkey::new_unique(),
+            nonce: 0,
+            init_code: Vec::new(),
+            call_data: Vec::new(),
+            call_gas_limit: 50_000,
+            verification_gas_limit: 20_000,
+            pre_verification_gas: 1_000,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            paymaster_and_data: Vec::new(),
+            signature: [0; 64],
+        }
+    }
+    
+    #[test]
+    fn common_entry_point_id_matches_this_program() {
+        assert_eq!(nexus_common::ENTRY_POINT_PROGRAM_ID, crate::ID);
+    }
+    
+    #[test]
+    fn gas_charge_adds_pre_verification_gas_to_measured_phases() {
+        let op = user_op(10, 3);
+        // 5k verification, 30k execution
+        let charge = GasCharge::measure(&op, 200_000, 195_000, 165_000).unwrap();
+        
+        assert_eq!(charge.gas_used, 5_000 + 30_000 + 1_000);
+        // Priority fee below the cap sets the price
+        assert_eq!(charge.cost, charge.gas_used * 3);
+    }
+    
+    #[test]
+    fn gas_charge_caps_each_phase_at_its_limit() {
+        let op = user_op(4, 9);
+        // 25k verification over its 20k limit, 80k execution over its 50k limit
+        let charge = GasCharge::measure(&op, 300_000, 275_000, 195_000).unwrap();
+        
+        assert_eq!(charge.gas_used, 20_000 + 50_000 + 1_000);
+        // `max_fee_per_gas` caps the priority fee
+        assert_eq!(charge.cost, charge.gas_used * 4);
+    }
+    
+    #[test]
+    fn gas_charge_rejects_cost_overflow() {
+        let op = user_op(u64::MAX, u64::MAX);
+        assert!(GasCharge::measure(&op, 10_000, 5_000, 0).is_err());
+    }
+    
+    #[test]
+    fn parameter_delays_are_capped() {
+        assert!(EntryPointParameter::TimelockDelay(MAX_DELAY).validate().is_ok());
+        assert!(EntryPointParameter::UnstakeDelay(0).validate().is_ok());
+        assert!(EntryPointParameter::TimelockDelay(MAX_DELAY + 1).validate().is_err());
+        assert!(EntryPointParameter::UnstakeDelay(i64::MAX).validate().is_err());
+        assert!(EntryPointParameter::UnstakeDelay(-1).validate().is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! Off-chain helpers for building wallet transactions.
+//!
+//! A legacy transaction can only name a few dozen accounts, which caps how
+//! many calls a single user operation can batch. These helpers manage
+//! lookup tables owned by the wallet PDA and compile operations into v0
+//! messages that load the calls' accounts through those tables.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    address_lookup_table_account::AddressLookupTableAccount,
+    ed25519_program,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, CompileError, VersionedMessage},
+    sysvar::instructions as sysvar_instructions,
+};
+use anchor_lang::{InstructionData, ToAccountMetas};
+
+use crate::{
+    accounts, instruction, lookup_table_address, UserOperation, WalletCall,
+    ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+};
+
+/// Addresses per `extend_lookup_table` instruction that keep the
+/// transaction within the packet size limit
+pub const MAX_ADDRESSES_PER_EXTEND: usize = 20;
+
+/// `create_lookup_table` instruction and the address of the table it creates
+pub fn create_lookup_table_ix(
+    wallet: Pubkey,
+    owner: Pubkey,
+    payer: Pubkey,
+    recent_slot: u64,
+) -> (Instruction, Pubkey) {
+    let (lookup_table, _) = lookup_table_address(&wallet, recent_slot);
+
+    let ix = Instruction {
+        program_id: crate::ID,
+        accounts: accounts::CreateLookupTable {
+            wallet,
+            owner,
+            payer,
+            lookup_table,
+            address_lookup_table_program: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+            system_program: System::id(),
+        }
+        .to_account_metas(None),
+        data: instruction::CreateLookupTable { recent_slot }.data(),
+    };
+
+    (ix, lookup_table)
+}
+
+/// `extend_lookup_table` instructions adding `addresses` in chunks of
+/// `MAX_ADDRESSES_PER_EXTEND`
+pub fn extend_lookup_table_ixs(
+    wallet: Pubkey,
+    owner: Pubkey,
+    payer: Pubkey,
+    lookup_table: Pubkey,
+    addresses: &[Pubkey],
+) -> Vec<Instruction> {
+    addresses
+        .chunks(MAX_ADDRESSES_PER_EXTEND)
+        .map(|chunk| Instruction {
+            program_id: crate::ID,
+            accounts: accounts::ExtendLookupTable {
+                wallet,
+                owner,
+                payer,
+                lookup_table,
+                address_lookup_table_program: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+                system_program: System::id(),
+            }
+            .to_account_metas(None),
+            data: instruction::ExtendLookupTable { addresses: chunk.to_vec() }.data(),
+        })
+        .collect()
+}
+
+pub fn deactivate_lookup_table_ix(wallet: Pubkey, owner: Pubkey, lookup_table: Pubkey) -> Instruction {
+    Instruction {
+        program_id: crate::ID,
+        accounts: accounts::DeactivateLookupTable {
+            wallet,
+            owner,
+            lookup_table,
+            address_lookup_table_program: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: instruction::DeactivateLookupTable {}.data(),
+    }
+}
+
+pub fn close_lookup_table_ix(
+    wallet: Pubkey,
+    owner: Pubkey,
+    lookup_table: Pubkey,
+    recipient: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: crate::ID,
+        accounts: accounts::CloseLookupTable {
+            wallet,
+            owner,
+            lookup_table,
+            recipient,
+            address_lookup_table_program: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+        }
+        .to_account_metas(None),
+        data: instruction::CloseLookupTable {}.data(),
+    }
+}
+
+/// Remaining accounts `execute_user_operation` needs for `calls`: every
+/// target and call account once, writable if any call writes it. The
+/// wallet itself is omitted since it is already the first account.
+pub fn call_account_metas(wallet: &Pubkey, calls: &[WalletCall]) -> Vec<AccountMeta> {
+    let mut metas: Vec<AccountMeta> = Vec::new();
+    let mut add = |pubkey: Pubkey, is_writable: bool| {
+        if pubkey == *wallet {
+            return;
+        }
+        match metas.iter_mut().find(|meta| meta.pubkey == pubkey) {
+            Some(meta) => meta.is_writable |= is_writable,
+            None => metas.push(AccountMeta {
+                pubkey,
+                is_signer: false,
+                is_writable,
+            }),
+        }
+    };
+
+    for call in calls {
+        // Lamports are credited to the target directly
+        add(call.target, call.value > 0);
+        for account in &call.accounts {
+            add(account.pubkey, account.is_writable);
+        }
+    }
+
+    metas
+}
+
+/// Addresses used by `calls` that none of `lookup_tables` holds yet, i.e.
+/// what to pass to `extend_lookup_table_ixs` before batching them
+pub fn missing_lookup_addresses(
+    wallet: &Pubkey,
+    calls: &[WalletCall],
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Vec<Pubkey> {
+    call_account_metas(wallet, calls)
+        .into_iter()
+        .map(|meta| meta.pubkey)
+        .filter(|pubkey| !lookup_tables.iter().any(|table| table.addresses.contains(pubkey)))
+        .collect()
+}
+
+/// Ed25519 program instruction verifying `signature` by `signer` over
+/// `message`, laid out the way the wallet looks for it: one signature with
+/// its key, signature and message inline
+pub fn ed25519_signature_ix(signer: &Pubkey, message: &[u8], signature: &[u8; 64]) -> Instruction {
+    // [count: u8, padding: u8] and one offsets record, then the values
+    const DATA_START: u16 = 2 + 14;
+    const THIS_INSTRUCTION: u16 = u16::MAX;
+    let public_key_offset = DATA_START;
+    let signature_offset = public_key_offset + 32;
+    let message_offset = signature_offset + 64;
+    
+    let mut data = vec![1, 0];
+    for value in [
+        signature_offset,
+        THIS_INSTRUCTION,
+        public_key_offset,
+        THIS_INSTRUCTION,
+        message_offset,
+        message.len() as u16,
+        THIS_INSTRUCTION,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(signer.as_ref());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+    
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: Vec::new(),
+        data,
+    }
+}
+
+/// `execute_user_operation` for an operation whose `call_data` encodes
+/// `calls`. `payer` funds the `PendingOperation` account if the operation
+/// is queued for co-signing. The transaction must also carry
+/// `ed25519_signature_ix` for the owner's signature over
+/// `user_op.canonical_hash()`.
+pub fn execute_user_operation_ix(
+    wallet: Pubkey,
+    user_op: UserOperation,
+    calls: &[WalletCall],
+    payer: Option<Pubkey>,
+) -> Instruction {
+    let pending_operation = payer.map(|_| {
+        Pubkey::find_program_address(
+            &[b"pending_op", wallet.as_ref(), &user_op.nonce.to_le_bytes()],
+            &crate::ID,
+        )
+        .0
+    });
+
+    let mut account_metas = accounts::ExecuteUserOperation {
+        wallet,
+        pending_operation,
+        payer,
+        system_program: payer.map(|_| System::id()),
+        paymaster: None,
+        paymaster_data_account: None,
+        paymaster_program: None,
+        instructions_sysvar: sysvar_instructions::ID,
+    }
+    .to_account_metas(None);
+    account_metas.extend(call_account_metas(&wallet, calls));
+
+    Instruction {
+        program_id: crate::ID,
+        accounts: account_metas,
+        data: instruction::ExecuteUserOperation {
+            user_op,
+            paymaster_data: None,
+        }
+        .data(),
+    }
+}
+
+/// Compiles wallet instructions into a v0 message that loads accounts
+/// through lookup tables. The message is returned unsigned.
+pub struct BatchTransactionBuilder {
+    payer: Pubkey,
+    instructions: Vec<Instruction>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+}
+
+impl BatchTransactionBuilder {
+    pub fn new(payer: Pubkey) -> Self {
+        Self {
+            payer,
+            instructions: Vec::new(),
+            lookup_tables: Vec::new(),
+        }
+    }
+
+    pub fn instruction(mut self, instruction: Instruction) -> Self {
+        self.instructions.push(instruction);
+        self
+    }
+
+    /// Add a lookup table; `addresses` must match the table's on-chain contents
+    pub fn lookup_table(mut self, lookup_table: AddressLookupTableAccount) -> Self {
+        self.lookup_tables.push(lookup_table);
+        self
+    }
+
+    pub fn build(&self, recent_blockhash: Hash) -> std::result::Result<VersionedMessage, CompileError> {
+        let message = v0::Message::try_compile(
+            &self.payer,
+            &self.instructions,
+            &self.lookup_tables,
+            recent_blockhash,
+        )?;
+
+        Ok(VersionedMessage::V0(message))
+    }
+}
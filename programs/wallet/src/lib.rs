@@ -3,13 +3,42 @@
 //! This program implements a PDA-based smart wallet for Solana that provides:
 //! - Account abstraction similar to ERC-4337
 //! - Cross-chain compatibility with EVM wallets  
-//! - Social recovery and multi-signature support
-//! - Integration with paymaster for sponsored transactions
+//! - Social recovery and multi-signature support, with key, wallet and
+//!   verifier-program guardians
+//! - Integration with paymaster for sponsored transactions, authorized by an
+//!   ed25519 signature from the paymaster owner
+//! - Delegated token allowances for dapp programs
+//! - Inactivity-based inheritance (dead man's switch)
+//! - Owner key rotation with two-step acceptance
+//! - Co-signing queue for high-value operations
+//! - Sub-wallets with parent-controlled budgets
+//! - Multiple concurrent spending windows, in lamports or USD
+//! - Dry-run simulation of user operations
+//! - Guardian-approved emergency sweep of a frozen wallet to a vault
+//! - Wallet-owned address lookup tables for large operations
+//! - Signature aggregators verifying bundled operations in one check
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    ed25519_program,
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    program::{get_return_data, invoke_signed, set_return_data, MAX_RETURN_DATA},
+    sysvar::instructions::{self as sysvar_instructions, load_instruction_at_checked},
+};
+use anchor_lang::system_program::{
+    create_account, transfer as transfer_lamports, CreateAccount, Transfer as SystemTransfer,
+};
+use nexus_paymaster::{program::NexusPaymaster, Paymaster, PostOpMode};
+pub use nexus_common::{UserOperation, ENTRY_POINT_PROGRAM_ID};
+use nexus_common::remaining_compute_units;
+use anchor_spl::token::{Mint, Token, TokenAccount, Transfer, transfer};
 
 declare_id!("G4vCcRCeB3rWpaTkkpsPWTf9Ar2a7qoWTJsWboztF6wS");
 
+#[cfg(not(target_os = "solana"))]
+pub mod client;
+
 #[program]
 pub mod nexus_wallet {
     use super::*;
@@ -21,18 +50,25 @@ pub mod nexus_wallet {
         recovery_hash: [u8; 32],
         daily_limit: u64,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let mut wallet = ctx.accounts.wallet.load_init()?;
+        
+        let current_time = Clock::get()?.unix_timestamp;
         
         wallet.owner = owner;
+        wallet.initial_owner = owner;
         wallet.recovery_hash = recovery_hash;
-        wallet.daily_limit = daily_limit;
-        wallet.daily_spent = 0;
-        wallet.last_reset = Clock::get()?.unix_timestamp;
+        wallet.spending_windows[0] = SpendingWindow::new(
+            daily_limit,
+            SECONDS_PER_DAY,
+            SpendingWindowMode::Rolling,
+            current_time,
+        );
         wallet.nonce = 0;
-        wallet.initialized = true;
-        wallet.is_frozen = false;
-        wallet.guardians = Vec::new();
-        wallet.pending_recovery = None;
+        wallet.initialized = 1;
+        wallet.is_frozen = 0;
+        wallet.guardian_count = 0;
+        wallet.last_activity = current_time;
+        wallet.bump = *ctx.bumps.get("wallet").ok_or(WalletError::InvalidWalletAccount)?;
         
         emit!(WalletInitialized {
             wallet: ctx.accounts.wallet.key(),
@@ -43,93 +79,465 @@ pub mod nexus_wallet {
         Ok(())
     }
 
+    /// Convert a wallet created with the original Borsh layout
+    /// (`LegacyWallet`) to the zero-copy `Wallet`, which no other
+    /// instruction can load until this has run. Only the wallet's owner can
+    /// migrate it, and pays the rent for the larger account. `initial_owner`
+    /// is the owner the wallet address was derived from, which differs from
+    /// the current owner after a recovery.
+    pub fn migrate_wallet(
+        ctx: Context<MigrateWallet>,
+        initial_owner: Pubkey,
+    ) -> Result<()> {
+        let wallet_info = ctx.accounts.wallet.to_account_info();
+        let space = 8 + std::mem::size_of::<Wallet>();
+        
+        let legacy = {
+            let data = wallet_info.try_borrow_data()?;
+            require!(data.len() < space, WalletError::AlreadyMigrated);
+            require!(
+                data.get(..8) == Some(&<Wallet as anchor_lang::Discriminator>::DISCRIMINATOR[..]),
+                WalletError::InvalidWalletAccount
+            );
+            LegacyWallet::deserialize(&mut &data[8..])?
+        };
+        require_keys_eq!(legacy.owner, ctx.accounts.owner.key(), WalletError::UnauthorizedOwner);
+        
+        let (expected_key, bump) = Pubkey::find_program_address(
+            &[b"wallet", initial_owner.as_ref(), &legacy.recovery_hash],
+            &crate::ID,
+        );
+        require_keys_eq!(wallet_info.key(), expected_key, WalletError::InvalidWalletAccount);
+        
+        let top_up = Rent::get()?.minimum_balance(space).saturating_sub(wallet_info.lamports());
+        if top_up > 0 {
+            transfer_lamports(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    SystemTransfer {
+                        from: ctx.accounts.owner.to_account_info(),
+                        to: wallet_info.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        
+        wallet_info.realloc(space, true)?;
+        wallet_info.try_borrow_mut_data()?[8..].fill(0);
+        
+        let loader = AccountLoader::<Wallet>::try_from(&wallet_info)?;
+        let mut wallet = loader.load_mut()?;
+        wallet.migrate_from(&legacy, initial_owner, bump, Clock::get()?.unix_timestamp);
+        
+        emit!(WalletMigrated {
+            wallet: wallet_info.key(),
+            owner: legacy.owner,
+        });
+        
+        Ok(())
+    }
+    
     /// Execute a user operation (similar to ERC-4337)
-    pub fn execute_user_operation(
-        ctx: Context<ExecuteUserOperation>,
+    ///
+    /// `call_data` is a Borsh-encoded `Vec<WalletCall>`; every account the
+    /// calls reference must be passed in remaining accounts. The transaction
+    /// must carry an Ed25519 program instruction verifying the owner's
+    /// signature over the operation's hash. Operations whose
+    /// lamport value exceeds the co-signing threshold are not executed but
+    /// queued in a `PendingOperation` PDA, which requires the optional
+    /// `pending_operation`, `payer` and `system_program` accounts. Tokens
+    /// moved by program calls are valued once the calls have run; if they
+    /// take the operation over the threshold it fails with
+    /// `CoSigningRequired`.
+    ///
+    /// A sponsoring paymaster is validated up front, and once the calls have
+    /// run the cost of the compute units they used is reported to it through
+    /// `post_op`; queued operations report nothing. This is accounting only:
+    /// no lamports leave the paymaster, and the transaction's fee payer pays
+    /// the fees. For the paymaster's deposit to pay, submit the operation
+    /// through the entry point's `handle_ops`.
+    pub fn execute_user_operation<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteUserOperation<'info>>,
         user_op: UserOperation,
         paymaster_data: Option<PaymasterData>,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let start = remaining_compute_units();
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let current_time = Clock::get()?.unix_timestamp;
         
-        // Verify wallet is not frozen
-        require!(!wallet.is_frozen, WalletError::WalletFrozen);
+        let ValidatedOperation { user_op_hash, calls, value, fee, .. } = validate_user_operation(
+            &mut wallet,
+            wallet_key,
+            &user_op,
+            paymaster_data.as_ref(),
+            SignatureCheck::Owner(&ctx.accounts.instructions_sysvar),
+        )?;
+        drop(wallet);
         
-        // Verify nonce
-        require!(user_op.nonce == wallet.nonce, WalletError::InvalidNonce);
+        let sponsorship = match &paymaster_data {
+            Some(paymaster_data) => Some((
+                paymaster_data.max_cost,
+                validate_paymaster(ctx.accounts, &user_op_hash, paymaster_data)?,
+            )),
+            None => None,
+        };
         
-        // Increment nonce
-        wallet.nonce += 1;
-        
-        // Check daily spending limit for non-paymaster operations
-        if paymaster_data.is_none() {
-            let current_time = Clock::get()?.unix_timestamp;
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        if wallet.requires_co_signing(&calls, value, current_time) {
+            // The value is charged when the queued operation executes
+            wallet.consume_spending(
+                &SpendAmounts { lamports: fee, tokens: Vec::new() },
+                ctx.remaining_accounts,
+                current_time,
+            )?;
             
-            // Reset daily spending if a day has passed
-            if current_time - wallet.last_reset >= 86400 {
-                wallet.daily_spent = 0;
-                wallet.last_reset = current_time;
-            }
+            let owner = wallet.owner;
+            let expires_at = current_time + wallet.co_sign_window;
+            drop(wallet);
             
-            // Check spending limit
-            require!(
-                wallet.daily_spent + user_op.max_fee_per_gas <= wallet.daily_limit,
-                WalletError::DailyLimitExceeded
-            );
+            queue_pending_operation(
+                ctx.accounts.pending_operation.as_ref(),
+                ctx.accounts.payer.as_ref(),
+                ctx.accounts.system_program.as_ref(),
+                PendingOperation {
+                    wallet: wallet_key,
+                    owner,
+                    rent_payer: Pubkey::default(),
+                    user_op_hash,
+                    nonce: user_op.nonce,
+                    value,
+                    expires_at,
+                    guardian_approvals: 0,
+                    second_factor_approved: false,
+                },
+            )?;
+            
+            emit!(UserOperationQueued {
+                wallet: wallet_key,
+                user_op_hash,
+                nonce: user_op.nonce,
+                value,
+                expires_at,
+            });
             
-            wallet.daily_spent += user_op.max_fee_per_gas;
+            return Ok(());
+        }
+        drop(wallet);
+        
+        let lamports = value.checked_add(fee).ok_or(WalletError::DailyLimitExceeded)?;
+        run_user_operation(
+            &ctx.accounts.wallet,
+            ctx.remaining_accounts,
+            &user_op_hash,
+            user_op.nonce,
+            &calls,
+            lamports,
+            Some(value),
+            current_time,
+        )?;
+        
+        if let Some((max_cost, context)) = sponsorship {
+            let gas_used = start
+                .saturating_sub(remaining_compute_units())
+                .saturating_add(user_op.pre_verification_gas);
+            let cost = gas_used.saturating_mul(user_op.gas_price());
+            require!(cost <= max_cost, WalletError::PaymasterCostExceeded);
+            record_paymaster_cost(ctx.accounts, context, cost)?;
+        }
+        
+        Ok(())
+    }
+
+    /// Dry-run a user operation: validate it, run its calls and charge its
+    /// spending, then abort with `SimulationComplete` so nothing persists.
+    /// The `SimulationReport` is left in the transaction's return data,
+    /// which `simulateTransaction` reports even though the instruction fails.
+    ///
+    /// Operations above the co-signing threshold are run as if already
+    /// co-signed so the report shows their effects. A call that fails aborts
+    /// the simulation with that call's error instead of producing a report.
+    pub fn simulate_user_operation<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteUserOperation<'info>>,
+        user_op: UserOperation,
+        paymaster_data: Option<PaymasterData>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let wallet_info = ctx.accounts.wallet.to_account_info();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        let validated = validate_user_operation(
+            &mut wallet,
+            wallet_key,
+            &user_op,
+            paymaster_data.as_ref(),
+            SignatureCheck::Owner(&ctx.accounts.instructions_sysvar),
+        )?;
+        drop(wallet);
+        
+        if let Some(paymaster_data) = &paymaster_data {
+            validate_paymaster(ctx.accounts, &validated.user_op_hash, paymaster_data)?;
+        }
+        
+        let wallet = ctx.accounts.wallet.load()?;
+        let queued = wallet.requires_co_signing(
+            &validated.calls,
+            validated.value,
+            current_time,
+        );
+        
+        let initial_owner = wallet.initial_owner;
+        let recovery_hash = wallet.recovery_hash;
+        let bump = wallet.bump;
+        drop(wallet);
+        
+        let seeds = &[
+            b"wallet".as_ref(),
+            initial_owner.as_ref(),
+            recovery_hash.as_ref(),
+            &[bump],
+        ];
+        
+        let balances_before = account_balances(&wallet_info, ctx.remaining_accounts);
+        let outcome = execute_calls(
+            &wallet_info,
+            &validated.calls,
+            ctx.remaining_accounts,
+            &[seeds],
+        )
+        .map_err(|failure| failure.error)?;
+        
+        let spend = SpendAmounts {
+            lamports: validated.value
+                .checked_add(validated.fee)
+                .ok_or(WalletError::DailyLimitExceeded)?,
+            tokens: outcome.token_outflows,
+        };
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let requires_co_signing = queued || wallet.exceeds_co_sign_threshold(
+            validated.value,
+            &spend.tokens,
+            ctx.remaining_accounts,
+            current_time,
+        )?;
+        let windows_before = wallet.spending_windows;
+        wallet.consume_spending(&spend, ctx.remaining_accounts, current_time)?;
+        
+        let mut limits = Vec::new();
+        for (index, (before, after)) in windows_before
+            .iter()
+            .zip(wallet.spending_windows.iter())
+            .enumerate()
+            .filter(|(_, (window, _))| window.is_active())
+        {
+            let status = after.status(index as u8, current_time)?;
+            limits.push(LimitConsumption {
+                index: index as u8,
+                denomination: status.denomination,
+                charged: status.used.saturating_sub(before.status(index as u8, current_time)?.used),
+                remaining: status.remaining,
+            });
         }
+        drop(wallet);
         
-        // Validate user operation signature
-        let user_op_hash = calculate_user_op_hash(&user_op)?;
+        let balance_deltas = account_balances(&wallet_info, ctx.remaining_accounts)
+            .into_iter()
+            .zip(balances_before)
+            .filter_map(|(after, before)| after.delta_from(&before))
+            .collect();
+        
+        let report = SimulationReport {
+            user_op_hash: validated.user_op_hash,
+            signer: validated.signer,
+            requires_co_signing,
+            limits,
+            call_results: outcome.call_results,
+            balance_deltas,
+        };
+        let data = report.try_to_vec()?;
         require!(
-            verify_signature(&user_op_hash, &user_op.signature, &wallet.owner)?,
-            WalletError::InvalidSignature
+            data.len() <= MAX_RETURN_DATA,
+            WalletError::SimulationReportTooLarge
         );
+        set_return_data(&data);
+        
+        err!(WalletError::SimulationComplete)
+    }
+
+    /// Read-only check of a user operation against the wallet's current
+    /// state, for the entry point to run before sponsoring or executing it.
+    /// Invalid operations are reported through `UserOpStatus` rather than
+    /// failing, so a bundle can skip them; nothing (not even the nonce) is
+    /// consumed. The owner's signature must be verified by an Ed25519
+    /// program instruction in the transaction, except when the wallet has a
+    /// signature aggregator: the entry point then verifies it through the
+    /// returned aggregator instead.
+    pub fn validate_user_op(
+        ctx: Context<ValidateUserOp>,
+        user_op: UserOperation,
+    ) -> Result<UserOpValidation> {
+        let wallet = ctx.accounts.wallet.load()?;
+        let user_op_hash = user_op.canonical_hash();
+        
+        let status = if user_op.sender != ctx.accounts.wallet.key() {
+            UserOpStatus::WrongSender
+        } else if wallet.is_frozen() {
+            UserOpStatus::WalletFrozen
+        } else if user_op.nonce != wallet.nonce {
+            UserOpStatus::InvalidNonce
+        } else if !wallet.has_aggregator()
+            && !verify_signature(
+                &ctx.accounts.instructions_sysvar,
+                &user_op_hash,
+                &user_op.signature,
+                &wallet.owner,
+            )?
+        {
+            UserOpStatus::InvalidSignature
+        } else {
+            let current_time = Clock::get()?.unix_timestamp;
+            match decode_calls(&user_op.call_data)
+                .and_then(|calls| calls_value(&calls).map(|value| (calls, value)))
+            {
+                Ok((calls, value)) if wallet.requires_co_signing(&calls, value, current_time) => {
+                    UserOpStatus::RequiresCoSigning
+                }
+                Ok(_) => UserOpStatus::Valid,
+                Err(_) => UserOpStatus::InvalidCallData,
+            }
+        };
         
-        emit!(UserOperationExecuted {
-            wallet: wallet.key(),
+        Ok(UserOpValidation {
             user_op_hash,
-            nonce: user_op.nonce,
-            success: true,
+            status,
+            aggregator: wallet.has_aggregator().then_some(wallet.aggregator),
+        })
+    }
+    
+    /// Execute a user operation whose signature the entry point verified
+    /// through the wallet's aggregator (`handle_aggregated_ops`), so the
+    /// owner's signature is not checked. Only the entry point can call this,
+    /// signing with its PDA, and `aggregator` and `user_op_hash` must be the
+    /// wallet's aggregator and this operation's hash. Operations above the
+    /// co-signing threshold must go through `execute_user_operation`
+    /// instead.
+    pub fn execute_aggregated_user_operation<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteAggregatedUserOperation<'info>>,
+        user_op: UserOperation,
+        aggregator: Pubkey,
+        user_op_hash: [u8; 32],
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        let ValidatedOperation { user_op_hash, calls, value, fee, .. } = validate_user_operation(
+            &mut wallet,
+            wallet_key,
+            &user_op,
+            None,
+            SignatureCheck::Aggregated { aggregator, user_op_hash },
+        )?;
+        require!(
+            !wallet.requires_co_signing(&calls, value, current_time),
+            WalletError::CoSigningRequired
+        );
+        drop(wallet);
+        
+        let lamports = value.checked_add(fee).ok_or(WalletError::DailyLimitExceeded)?;
+        run_user_operation(
+            &ctx.accounts.wallet,
+            ctx.remaining_accounts,
+            &user_op_hash,
+            user_op.nonce,
+            &calls,
+            lamports,
+            Some(value),
+            current_time,
+        )
+    }
+    
+    /// Set or clear the signature aggregator trusted to verify the owner's
+    /// signatures on operations bundled through `handle_aggregated_ops`
+    pub fn set_aggregator(
+        ctx: Context<ModifyAggregator>,
+        aggregator: Option<Pubkey>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        
+        let previous = wallet.has_aggregator().then_some(wallet.aggregator);
+        wallet.aggregator = aggregator.unwrap_or_default();
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(AggregatorChanged {
+            wallet: wallet_key,
+            previous,
+            aggregator,
         });
         
         Ok(())
     }
-
-    /// Add a guardian for social recovery
+    
+    /// Add a guardian for social recovery. For `GuardianKind::Verifier`,
+    /// `guardian` is the verifier program id.
     pub fn add_guardian(
         ctx: Context<ModifyGuardians>,
         guardian: Pubkey,
+        kind: GuardianKind,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        require!((wallet.guardian_count as usize) < MAX_GUARDIANS, WalletError::TooManyGuardians);
+        require!(wallet.guardian_index(&guardian).is_none(), WalletError::GuardianAlreadyExists);
         
-        require!(wallet.guardians.len() < 10, WalletError::TooManyGuardians);
-        require!(!wallet.guardians.contains(&guardian), WalletError::GuardianAlreadyExists);
+        require!(guardian != wallet_key, WalletError::InvalidGuardian);
         
-        wallet.guardians.push(guardian);
+        let slot = wallet.guardian_count as usize;
+        wallet.guardians[slot] = guardian;
+        wallet.guardian_kinds[slot] = kind as u8;
+        wallet.guardian_attestors[slot] = match kind {
+            GuardianKind::Verifier => {
+                Pubkey::find_program_address(&[GUARDIAN_VERIFIER_SEED, wallet_key.as_ref()], &guardian).0
+            }
+            GuardianKind::Key | GuardianKind::Wallet => Pubkey::default(),
+        };
+        wallet.guardian_count += 1;
+        record_owner_activity(&mut wallet, wallet_key)?;
         
         emit!(GuardianAdded {
-            wallet: wallet.key(),
+            wallet: wallet_key,
             guardian,
+            kind,
         });
         
         Ok(())
     }
 
-    /// Remove a guardian
+    /// Remove a guardian. Not allowed while the wallet is frozen, which also
+    /// covers any vault sweep collecting approvals.
     pub fn remove_guardian(
         ctx: Context<ModifyGuardians>,
         guardian: Pubkey,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
         
-        let index = wallet.guardians.iter().position(|&g| g == guardian)
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        let index = wallet.guardian_index(&guardian)
             .ok_or(WalletError::GuardianNotFound)?;
         
-        wallet.guardians.remove(index);
+        wallet.remove_guardian_at(index);
+        record_owner_activity(&mut wallet, wallet_key)?;
         
         emit!(GuardianRemoved {
-            wallet: wallet.key(),
+            wallet: wallet_key,
             guardian,
         });
         
@@ -141,20 +549,24 @@ pub mod nexus_wallet {
         ctx: Context<InitiateRecovery>,
         new_owner: Pubkey,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
         let guardian = &ctx.accounts.guardian;
         
-        require!(wallet.guardians.contains(&guardian.key()), WalletError::UnauthorizedGuardian);
-        require!(wallet.pending_recovery.is_none(), WalletError::RecoveryInProgress);
+        let index = wallet.guardian_signer_index(guardian)
+            .ok_or(WalletError::UnauthorizedGuardian)?;
+        require!(!wallet.pending_recovery.is_active(), WalletError::RecoveryInProgress);
         
-        wallet.pending_recovery = Some(RecoveryRequest {
+        wallet.pending_recovery = RecoveryRequest {
             new_owner,
-            guardian_approvals: vec![guardian.key()],
             initiated_at: Clock::get()?.unix_timestamp,
-        });
+            guardian_approvals: 1 << index,
+            is_active: 1,
+            _padding: [0; 3],
+        };
         
         emit!(RecoveryInitiated {
-            wallet: wallet.key(),
+            wallet: wallet_key,
             new_owner,
             guardian: guardian.key(),
         });
@@ -166,33 +578,30 @@ pub mod nexus_wallet {
     pub fn approve_recovery(
         ctx: Context<ApproveRecovery>,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
         let guardian = &ctx.accounts.guardian;
         
-        require!(wallet.guardians.contains(&guardian.key()), WalletError::UnauthorizedGuardian);
-        
-        // Extract guardians length before mutable borrow
-        let guardians_count = wallet.guardians.len();
-        
-        let recovery = wallet.pending_recovery.as_mut()
-            .ok_or(WalletError::NoRecoveryInProgress)?;
+        let index = wallet.guardian_signer_index(guardian)
+            .ok_or(WalletError::UnauthorizedGuardian)?;
+        require!(wallet.pending_recovery.is_active(), WalletError::NoRecoveryInProgress);
         
-        require!(!recovery.guardian_approvals.contains(&guardian.key()), WalletError::AlreadyApproved);
+        let bit = 1u32 << index;
+        require!(wallet.pending_recovery.guardian_approvals & bit == 0, WalletError::AlreadyApproved);
         
-        recovery.guardian_approvals.push(guardian.key());
+        wallet.pending_recovery.guardian_approvals |= bit;
         
         // Check if we have enough approvals (majority of guardians)
-        let required_approvals = (guardians_count / 2) + 1;
-        let current_approvals = recovery.guardian_approvals.len();
+        let required_approvals = wallet.required_approvals();
+        let current_approvals = wallet.pending_recovery.approval_count();
         
         if current_approvals >= required_approvals {
-            // Execute recovery - extract values before mutating wallet
-            let new_owner = recovery.new_owner;
-            let wallet_key = wallet.key();
+            let new_owner = wallet.pending_recovery.new_owner;
             let old_owner = wallet.owner;
             
             wallet.owner = new_owner;
-            wallet.pending_recovery = None;
+            wallet.pending_recovery = RecoveryRequest::default();
+            wallet.pending_owner_rotation = OwnerRotation::default();
             wallet.nonce += 1; // Invalidate any pending operations
             
             emit!(RecoveryCompleted {
@@ -202,7 +611,7 @@ pub mod nexus_wallet {
             });
         } else {
             emit!(RecoveryApproved {
-                wallet: wallet.key(),
+                wallet: wallet_key,
                 guardian: guardian.key(),
                 approvals: current_approvals as u8,
                 required: required_approvals as u8,
@@ -212,312 +621,5069 @@ pub mod nexus_wallet {
         Ok(())
     }
 
-    /// Emergency freeze wallet
+    /// Emergency freeze wallet. A frozen wallet cannot be frozen again, so
+    /// a single guardian cannot keep pushing back the owner's unfreeze.
     pub fn freeze_wallet(
         ctx: Context<FreezeWallet>,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
         let guardian = &ctx.accounts.guardian;
         
-        require!(wallet.guardians.contains(&guardian.key()), WalletError::UnauthorizedGuardian);
+        require!(
+            wallet.guardian_signer_index(guardian).is_some(),
+            WalletError::UnauthorizedGuardian
+        );
         
-        wallet.is_frozen = true;
+        wallet.freeze(Clock::get()?.unix_timestamp)?;
         
         emit!(WalletFrozen {
-            wallet: wallet.key(),
+            wallet: wallet_key,
             guardian: guardian.key(),
         });
         
         Ok(())
     }
 
-    /// Unfreeze wallet (requires owner)
+    /// Unfreeze wallet (requires owner). Only possible `UNFREEZE_DELAY`
+    /// after the freeze, so guardians have time to recover the wallet
+    /// or sweep it before a stolen owner key can lift the freeze.
     pub fn unfreeze_wallet(
         ctx: Context<UnfreezeWallet>,
     ) -> Result<()> {
-        let wallet = &mut ctx.accounts.wallet;
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!(wallet.is_frozen(), WalletError::WalletNotFrozen);
+        require!(
+            Clock::get()?.unix_timestamp >= wallet.unfreeze_time(),
+            WalletError::UnfreezeDelayNotElapsed
+        );
         
-        wallet.is_frozen = false;
+        wallet.is_frozen = 0;
+        wallet.vault_sweep_approvals = 0;
+        record_owner_activity(&mut wallet, wallet_key)?;
         
         emit!(WalletUnfrozen {
-            wallet: wallet.key(),
+            wallet: wallet_key,
             owner: wallet.owner,
         });
         
         Ok(())
     }
-}
-
-// Account Structures
-#[account]
-#[derive(InitSpace)]
-pub struct Wallet {
-    pub owner: Pubkey,                    // 32
-    pub recovery_hash: [u8; 32],          // 32
-    pub daily_limit: u64,                 // 8
-    pub daily_spent: u64,                 // 8
-    pub last_reset: i64,                  // 8
-    pub nonce: u64,                       // 8
-    pub initialized: bool,                // 1
-    pub is_frozen: bool,                  // 1
-    #[max_len(10)]
-    pub guardians: Vec<Pubkey>,           // 4 + (10 * 32) = 324
-    pub pending_recovery: Option<RecoveryRequest>, // 1 + 88 = 89
-}
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace)]
-pub struct RecoveryRequest {
-    pub new_owner: Pubkey,                // 32
-    #[max_len(10)]
-    pub guardian_approvals: Vec<Pubkey>,  // 4 + (10 * 32) = 324
-    pub initiated_at: i64,                // 8
-}
+    /// Propose the emergency vault that guardians can sweep a frozen wallet
+    /// into. Takes effect via `apply_vault_change` after `VAULT_CHANGE_DELAY`.
+    pub fn propose_vault(
+        ctx: Context<ModifyVault>,
+        vault: Pubkey,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        require!(
+            vault != Pubkey::default() && vault != wallet_key && vault != wallet.vault,
+            WalletError::InvalidVault
+        );
+        
+        let executable_at = Clock::get()?.unix_timestamp + VAULT_CHANGE_DELAY;
+        wallet.pending_vault = vault;
+        wallet.vault_change_at = executable_at;
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(VaultChangeProposed {
+            wallet: wallet_key,
+            current_vault: wallet.vault,
+            new_vault: vault,
+            executable_at,
+            guardians: wallet.guardian_keys().to_vec(),
+        });
+        
+        Ok(())
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct UserOperation {
-    pub sender: Pubkey,
-    pub nonce: u64,
-    pub init_code: Vec<u8>,
-    pub call_data: Vec<u8>,
-    pub call_gas_limit: u64,
-    pub verification_gas_limit: u64,
-    pub pre_verification_gas: u64,
-    pub max_fee_per_gas: u64,
-    pub max_priority_fee_per_gas: u64,
-    pub paymaster_and_data: Vec<u8>,
-    pub signature: [u8; 64],
-}
+    /// Make a proposed vault active once its delay has passed (callable by anyone)
+    pub fn apply_vault_change(
+        ctx: Context<ApplyVaultChange>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        require!(wallet.has_pending_vault(), WalletError::NoVaultChange);
+        require!(
+            Clock::get()?.unix_timestamp >= wallet.vault_change_at,
+            WalletError::VaultChangeDelayNotElapsed
+        );
+        
+        let old_vault = wallet.vault;
+        wallet.vault = wallet.pending_vault;
+        wallet.pending_vault = Pubkey::default();
+        wallet.vault_change_at = 0;
+        
+        emit!(VaultChanged {
+            wallet: wallet_key,
+            old_vault,
+            new_vault: wallet.vault,
+        });
+        
+        Ok(())
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct PaymasterData {
-    pub paymaster: Pubkey,
-    pub token_mint: Option<Pubkey>,
-    pub max_cost: u64,
-    pub signature: [u8; 64],
-}
+    /// Cancel a pending vault change (owner or any guardian)
+    pub fn cancel_vault_change(
+        ctx: Context<CancelVaultChange>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let authority = &ctx.accounts.authority;
+        
+        let is_owner = authority.key() == wallet.owner;
+        require!(
+            is_owner || wallet.guardian_signer_index(authority).is_some(),
+            WalletError::UnauthorizedGuardian
+        );
+        require!(wallet.has_pending_vault(), WalletError::NoVaultChange);
+        
+        let cancelled_vault = wallet.pending_vault;
+        wallet.pending_vault = Pubkey::default();
+        wallet.vault_change_at = 0;
+        if is_owner {
+            record_owner_activity(&mut wallet, wallet_key)?;
+        }
+        
+        emit!(VaultChangeCancelled {
+            wallet: wallet_key,
+            cancelled_vault,
+            cancelled_by: authority.key(),
+        });
+        
+        Ok(())
+    }
 
-// Context Structures
-#[derive(Accounts)]
-#[instruction(owner: Pubkey, recovery_hash: [u8; 32], daily_limit: u64)]
-pub struct InitializeWallet<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + Wallet::INIT_SPACE,
-        seeds = [b"wallet", owner.as_ref(), &recovery_hash],
-        bump
-    )]
-    pub wallet: Account<'info, Wallet>,
-    
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
+    /// Guardian vote to sweep a frozen wallet into its vault. Votes are
+    /// cleared when the wallet is unfrozen.
+    pub fn approve_vault_sweep(
+        ctx: Context<ApproveVaultSweep>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let guardian = &ctx.accounts.guardian;
+        
+        let index = wallet.guardian_signer_index(guardian)
+            .ok_or(WalletError::UnauthorizedGuardian)?;
+        require!(wallet.is_frozen(), WalletError::WalletNotFrozen);
+        require!(wallet.vault != Pubkey::default(), WalletError::NoVault);
+        
+        let bit = 1u32 << index;
+        require!(wallet.vault_sweep_approvals & bit == 0, WalletError::AlreadyApproved);
+        wallet.vault_sweep_approvals |= bit;
+        
+        emit!(VaultSweepApproved {
+            wallet: wallet_key,
+            guardian: guardian.key(),
+            approvals: wallet.vault_sweep_approvals.count_ones() as u8,
+            required: wallet.required_approvals() as u8,
+        });
+        
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct ExecuteUserOperation<'info> {
-    #[account(
-        mut,
-        seeds = [b"wallet", wallet.owner.as_ref(), &wallet.recovery_hash],
-        bump
-    )]
-    pub wallet: Account<'info, Wallet>,
-}
+    /// Move lamports above rent, and the token balances passed as
+    /// (wallet token account, vault token account) pairs in
+    /// `remaining_accounts`, into the vault. Callable by anyone once a
+    /// guardian majority has approved; may be repeated to sweep more tokens.
+    pub fn sweep_to_vault<'info>(
+        ctx: Context<'_, '_, '_, 'info, SweepToVault<'info>>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let wallet = ctx.accounts.wallet.load()?;
+        
+        require!(wallet.is_frozen(), WalletError::WalletNotFrozen);
+        require!(wallet.vault != Pubkey::default(), WalletError::NoVault);
+        require!(
+            wallet.vault_sweep_approvals.count_ones() >= wallet.required_approvals(),
+            WalletError::InsufficientApprovals
+        );
+        
+        let vault = wallet.vault;
+        let initial_owner = wallet.initial_owner;
+        let recovery_hash = wallet.recovery_hash;
+        let bump = wallet.bump;
+        drop(wallet);
+        
+        let seeds = &[
+            b"wallet".as_ref(),
+            initial_owner.as_ref(),
+            recovery_hash.as_ref(),
+            &[bump],
+        ];
+        let wallet_info = ctx.accounts.wallet.to_account_info();
+        
+        if !ctx.remaining_accounts.is_empty() {
+            let token_program = ctx.accounts.token_program.as_ref()
+                .ok_or(WalletError::MissingTokenProgram)?;
+            
+            sweep_token_accounts(
+                &wallet_info,
+                ctx.remaining_accounts,
+                &token_program.to_account_info(),
+                Some(&vault),
+                &[seeds],
+            )?;
+        }
+        
+        let rent_exempt = Rent::get()?.minimum_balance(wallet_info.data_len());
+        let lamports = wallet_info.lamports().saturating_sub(rent_exempt);
+        
+        **wallet_info.try_borrow_mut_lamports()? -= lamports;
+        **ctx.accounts.vault.try_borrow_mut_lamports()? += lamports;
+        
+        emit!(VaultSwept {
+            wallet: wallet_key,
+            vault,
+            lamports,
+            token_accounts: (ctx.remaining_accounts.len() / 2) as u8,
+        });
+        
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct ModifyGuardians<'info> {
-    #[account(
-        mut,
-        seeds = [b"wallet", wallet.owner.as_ref(), &wallet.recovery_hash],
-        bump,
-        has_one = owner
-    )]
-    pub wallet: Account<'info, Wallet>,
-    
-    pub owner: Signer<'info>,
-}
+    /// Approve a spender to pull up to `amount` of `mint` from the wallet.
+    /// Re-approving an existing spender overwrites the previous allowance.
+    ///
+    /// The approved amount counts towards the co-signing threshold like a
+    /// transfer of it. An approval that goes over the threshold must be
+    /// signed by `required_co_signatures` guardians or the second factor,
+    /// passed as signers in remaining accounts along with the price feeds
+    /// valuing `mint`.
+    pub fn approve_allowance<'info>(
+        ctx: Context<'_, '_, '_, 'info, ApproveAllowance<'info>>,
+        spender: Pubkey,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        if let Some(expiry) = expires_at {
+            require!(expiry > current_time, WalletError::InvalidExpiry);
+        }
+        
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        
+        let approved = [(ctx.accounts.mint.key(), amount)];
+        if wallet.exceeds_co_sign_threshold(0, &approved, ctx.remaining_accounts, current_time)? {
+            require!(
+                wallet.co_signature_count(ctx.remaining_accounts) >= wallet.required_co_signatures as u32,
+                WalletError::CoSigningRequired
+            );
+        } else {
+            wallet.record_unsigned_spend(0, &approved, ctx.remaining_accounts, current_time)?;
+        }
+        
+        record_owner_activity(&mut wallet, wallet_key)?;
+        drop(wallet);
+        
+        let allowance = &mut ctx.accounts.allowance;
+        
+        allowance.wallet = wallet_key;
+        allowance.spender = spender;
+        allowance.mint = ctx.accounts.mint.key();
+        allowance.amount = amount;
+        allowance.expires_at = expires_at;
+        
+        emit!(AllowanceApproved {
+            wallet: allowance.wallet,
+            spender,
+            mint: allowance.mint,
+            amount,
+            expires_at,
+        });
+        
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct InitiateRecovery<'info> {
-    #[account(
-        mut,
-        seeds = [b"wallet", wallet.owner.as_ref(), &wallet.recovery_hash],
-        bump
-    )]
-    pub wallet: Account<'info, Wallet>,
-    
-    pub guardian: Signer<'info>,
-}
+    /// Revoke an allowance and return its rent to the owner
+    pub fn revoke_allowance(
+        ctx: Context<RevokeAllowance>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        record_owner_activity(&mut *ctx.accounts.wallet.load_mut()?, wallet_key)?;
+        
+        let allowance = &ctx.accounts.allowance;
+        
+        emit!(AllowanceRevoked {
+            wallet: allowance.wallet,
+            spender: allowance.spender,
+            mint: allowance.mint,
+        });
+        
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct ApproveRecovery<'info> {
-    #[account(
-        mut,
-        seeds = [b"wallet", wallet.owner.as_ref(), &wallet.recovery_hash],
-        bump
-    )]
-    pub wallet: Account<'info, Wallet>,
-    
-    pub guardian: Signer<'info>,
-}
+    /// Pull tokens from the wallet against an allowance (called by the
+    /// spender). The tokens are charged to the wallet's spending windows;
+    /// price feeds for USD windows go in remaining accounts.
+    pub fn spend_allowance<'info>(
+        ctx: Context<'_, '_, '_, 'info, SpendAllowance<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let allowance = &mut ctx.accounts.allowance;
+        
+        wallet.spend_allowance(
+            allowance,
+            amount,
+            ctx.remaining_accounts,
+            Clock::get()?.unix_timestamp,
+        )?;
+        
+        let initial_owner = wallet.initial_owner;
+        let recovery_hash = wallet.recovery_hash;
+        let bump = wallet.bump;
+        drop(wallet);
+        
+        let seeds = &[
+            b"wallet".as_ref(),
+            initial_owner.as_ref(),
+            recovery_hash.as_ref(),
+            &[bump],
+        ];
+        
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.source_token_account.to_account_info(),
+            to: ctx.accounts.destination_token_account.to_account_info(),
+            authority: ctx.accounts.wallet.to_account_info(),
+        };
+        
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &[seeds],
+            ),
+            amount,
+        )?;
+        
+        emit!(AllowanceSpent {
+            wallet: ctx.accounts.wallet.key(),
+            spender: allowance.spender,
+            mint: allowance.mint,
+            amount,
+            remaining: allowance.amount,
+        });
+        
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct FreezeWallet<'info> {
-    #[account(
-        mut,
-        seeds = [b"wallet", wallet.owner.as_ref(), &wallet.recovery_hash],
+    /// Configure a beneficiary who may claim the wallet after a period of owner inactivity
+    pub fn configure_inheritance(
+        ctx: Context<ConfigureInheritance>,
+        beneficiary: Pubkey,
+        inactivity_period: i64,
+        grace_period: i64,
+        mode: InheritanceMode,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!(
+            inactivity_period >= MIN_INACTIVITY_PERIOD
+                && (0..=MAX_GRACE_PERIOD).contains(&grace_period),
+            WalletError::InvalidInheritancePeriod
+        );
+        require!(beneficiary != wallet.owner, WalletError::InvalidBeneficiary);
+        
+        record_owner_activity(&mut wallet, wallet_key)?;
+        wallet.inheritance = InheritanceConfig {
+            beneficiary,
+            inactivity_period,
+            grace_period,
+            claim_started_at: 0,
+            mode: mode as u8,
+            is_configured: 1,
+            _padding: [0; 6],
+        };
+        
+        emit!(InheritanceConfigured {
+            wallet: wallet_key,
+            beneficiary,
+            inactivity_period,
+            grace_period,
+            mode,
+        });
+        
+        Ok(())
+    }
+
+    /// Remove the inheritance configuration (and any pending claim)
+    pub fn clear_inheritance(
+        ctx: Context<ConfigureInheritance>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        record_owner_activity(&mut wallet, wallet_key)?;
+        wallet.inheritance = InheritanceConfig::default();
+        
+        emit!(InheritanceCleared {
+            wallet: wallet_key,
+        });
+        
+        Ok(())
+    }
+
+    /// Start an inheritance claim once the owner has been inactive long enough
+    pub fn start_inheritance_claim(
+        ctx: Context<InheritanceClaim>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let beneficiary = &ctx.accounts.beneficiary;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(wallet.inheritance.is_configured(), WalletError::InheritanceNotConfigured);
+        require!(wallet.inheritance.beneficiary == beneficiary.key(), WalletError::InvalidBeneficiary);
+        require!(!wallet.inheritance.has_claim(), WalletError::InheritanceClaimInProgress);
+        require!(
+            current_time - wallet.last_activity >= wallet.inheritance.inactivity_period,
+            WalletError::OwnerStillActive
+        );
+        
+        let claimable_at = current_time
+            .checked_add(wallet.inheritance.grace_period)
+            .ok_or(WalletError::InvalidInheritancePeriod)?;
+        wallet.inheritance.claim_started_at = current_time;
+        
+        emit!(InheritanceClaimStarted {
+            wallet: wallet_key,
+            beneficiary: beneficiary.key(),
+            claimable_at,
+        });
+        
+        Ok(())
+    }
+
+    /// Cancel a pending inheritance claim (any owner activity also does this)
+    pub fn cancel_inheritance_claim(
+        ctx: Context<ConfigureInheritance>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!(wallet.inheritance.has_claim(), WalletError::NoInheritanceClaim);
+        
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        Ok(())
+    }
+
+    /// Complete an inheritance claim after the grace period has elapsed.
+    /// Depending on the configured mode this either hands ownership to the
+    /// beneficiary or sweeps the wallet's assets to them.
+    ///
+    /// For `SweepAssets`, remaining accounts are (source, destination) token
+    /// account pairs; each source must be owned by the wallet.
+    pub fn finalize_inheritance_claim<'info>(
+        ctx: Context<'_, '_, '_, 'info, FinalizeInheritanceClaim<'info>>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let beneficiary = &ctx.accounts.beneficiary;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(wallet.inheritance.is_configured(), WalletError::InheritanceNotConfigured);
+        require!(wallet.inheritance.has_claim(), WalletError::NoInheritanceClaim);
+        require!(wallet.inheritance.beneficiary == beneficiary.key(), WalletError::InvalidBeneficiary);
+        let claimable_at = wallet.inheritance.claim_started_at
+            .checked_add(wallet.inheritance.grace_period)
+            .ok_or(WalletError::InvalidInheritancePeriod)?;
+        require!(current_time >= claimable_at, WalletError::GracePeriodNotElapsed);
+        
+        let mode = wallet.inheritance.mode()?;
+        let old_owner = wallet.owner;
+        
+        wallet.inheritance = InheritanceConfig::default();
+        wallet.last_activity = current_time;
+        
+        match mode {
+            InheritanceMode::TransferOwnership => {
+                wallet.owner = beneficiary.key();
+                wallet.pending_recovery = RecoveryRequest::default();
+                wallet.pending_owner_rotation = OwnerRotation::default();
+                wallet.nonce += 1; // Invalidate any pending operations
+            }
+            InheritanceMode::SweepAssets => {
+                let initial_owner = wallet.initial_owner;
+                let recovery_hash = wallet.recovery_hash;
+                let bump = wallet.bump;
+                drop(wallet);
+                
+                let seeds = &[
+                    b"wallet".as_ref(),
+                    initial_owner.as_ref(),
+                    recovery_hash.as_ref(),
+                    &[bump],
+                ];
+                let wallet_info = ctx.accounts.wallet.to_account_info();
+                
+                if !ctx.remaining_accounts.is_empty() {
+                    let token_program = ctx.accounts.token_program.as_ref()
+                        .ok_or(WalletError::MissingTokenProgram)?;
+                    
+                    sweep_token_accounts(
+                        &wallet_info,
+                        ctx.remaining_accounts,
+                        &token_program.to_account_info(),
+                        None,
+                        &[seeds],
+                    )?;
+                }
+                
+                let rent_exempt = Rent::get()?.minimum_balance(wallet_info.data_len());
+                let sweepable = wallet_info.lamports().saturating_sub(rent_exempt);
+                
+                **wallet_info.try_borrow_mut_lamports()? -= sweepable;
+                **beneficiary.to_account_info().try_borrow_mut_lamports()? += sweepable;
+            }
+        }
+        
+        emit!(InheritanceClaimed {
+            wallet: wallet_key,
+            old_owner,
+            beneficiary: beneficiary.key(),
+            mode,
+        });
+        
+        Ok(())
+    }
+
+    /// Set the delay between `propose_owner` and `accept_ownership`.
+    /// Increases apply immediately; a decrease only takes effect once the
+    /// current delay has elapsed, so a stolen owner key cannot shorten the
+    /// delay and rotate in the same breath.
+    pub fn set_owner_rotation_delay(
+        ctx: Context<ModifyOwnerRotation>,
+        delay: i64,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(
+            (0..=MAX_OWNER_ROTATION_DELAY).contains(&delay),
+            WalletError::InvalidRotationDelay
+        );
+        
+        wallet.settle_rotation_delay(current_time);
+        let old_delay = wallet.owner_rotation_delay;
+        let effective_at = wallet.schedule_rotation_delay(delay, current_time);
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(OwnerRotationDelayUpdated {
+            wallet: wallet_key,
+            old_delay,
+            new_delay: delay,
+            effective_at,
+        });
+        
+        Ok(())
+    }
+
+    /// Propose a new owner key; it takes effect once the new key calls
+    /// `accept_ownership` after the configured rotation delay
+    pub fn propose_owner(
+        ctx: Context<ModifyOwnerRotation>,
+        new_owner: Pubkey,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        require!(
+            new_owner != wallet.owner && new_owner != Pubkey::default(),
+            WalletError::InvalidNewOwner
+        );
+        
+        wallet.settle_rotation_delay(current_time);
+        let executable_at = current_time + wallet.owner_rotation_delay;
+        wallet.pending_owner_rotation = OwnerRotation {
+            new_owner,
+            proposed_at: current_time,
+            executable_at,
+        };
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        // Guardians are listed so indexers can notify each of them
+        emit!(OwnerRotationProposed {
+            wallet: wallet_key,
+            current_owner: wallet.owner,
+            new_owner,
+            executable_at,
+            guardians: wallet.guardian_keys().to_vec(),
+        });
+        
+        Ok(())
+    }
+
+    /// Complete an owner rotation (signed by the proposed owner)
+    pub fn accept_ownership(
+        ctx: Context<AcceptOwnership>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let new_owner = &ctx.accounts.new_owner;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        require!(wallet.pending_owner_rotation.is_pending(), WalletError::NoOwnerRotation);
+        require!(
+            wallet.pending_owner_rotation.new_owner == new_owner.key(),
+            WalletError::InvalidNewOwner
+        );
+        require!(
+            current_time >= wallet.pending_owner_rotation.executable_at,
+            WalletError::RotationDelayNotElapsed
+        );
+        
+        let old_owner = wallet.owner;
+        
+        wallet.owner = new_owner.key();
+        wallet.pending_owner_rotation = OwnerRotation::default();
+        wallet.nonce += 1; // Invalidate any pending operations
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(OwnershipTransferred {
+            wallet: wallet_key,
+            old_owner,
+            new_owner: new_owner.key(),
+        });
+        
+        Ok(())
+    }
+
+    /// Cancel a pending owner rotation (owner or any guardian)
+    pub fn cancel_owner_rotation(
+        ctx: Context<CancelOwnerRotation>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let authority = &ctx.accounts.authority;
+        
+        let is_owner = authority.key() == wallet.owner;
+        require!(
+            is_owner || wallet.guardian_signer_index(authority).is_some(),
+            WalletError::UnauthorizedGuardian
+        );
+        require!(wallet.pending_owner_rotation.is_pending(), WalletError::NoOwnerRotation);
+        
+        let new_owner = wallet.pending_owner_rotation.new_owner;
+        wallet.pending_owner_rotation = OwnerRotation::default();
+        if is_owner {
+            record_owner_activity(&mut wallet, wallet_key)?;
+        }
+        
+        emit!(OwnerRotationCancelled {
+            wallet: wallet_key,
+            new_owner,
+            cancelled_by: authority.key(),
+        });
+        
+        Ok(())
+    }
+
+    /// Configure co-signing for operations above `threshold` lamports.
+    /// A threshold of zero disables the queue. With `co_sign_program_calls`
+    /// every program call is queued as well, whatever it moves. See
+    /// `Wallet::requires_co_signing` for which operations are queued.
+    pub fn configure_co_signing(
+        ctx: Context<ModifyCoSigning>,
+        threshold: u64,
+        window: i64,
+        required_co_signatures: u8,
+        second_factor: Option<Pubkey>,
+        co_sign_program_calls: bool,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        if threshold > 0 {
+            let available = wallet.guardian_count as usize + second_factor.is_some() as usize;
+            require!(
+                required_co_signatures > 0 && required_co_signatures as usize <= available,
+                WalletError::InvalidCoSigningConfig
+            );
+            require!(
+                (1..=MAX_CO_SIGN_WINDOW).contains(&window),
+                WalletError::InvalidCoSigningConfig
+            );
+        }
+        
+        wallet.co_sign_threshold = threshold;
+        wallet.co_sign_window = window;
+        wallet.required_co_signatures = required_co_signatures;
+        wallet.second_factor = second_factor.unwrap_or_default();
+        wallet.co_sign_program_calls = co_sign_program_calls as u8;
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(CoSigningConfigured {
+            wallet: wallet_key,
+            threshold,
+            window,
+            required_co_signatures,
+            second_factor,
+            co_sign_program_calls,
+        });
+        
+        Ok(())
+    }
+
+    /// Co-sign a queued operation (guardian or second factor)
+    pub fn approve_pending_operation(
+        ctx: Context<ApprovePendingOperation>,
+    ) -> Result<()> {
+        let wallet = ctx.accounts.wallet.load()?;
+        let pending = &mut ctx.accounts.pending_operation;
+        let co_signer = ctx.accounts.co_signer.key();
+        
+        require!(
+            Clock::get()?.unix_timestamp < pending.expires_at,
+            WalletError::PendingOperationExpired
+        );
+        
+        if wallet.second_factor != Pubkey::default() && co_signer == wallet.second_factor {
+            require!(!pending.second_factor_approved, WalletError::AlreadyApproved);
+            pending.second_factor_approved = true;
+        } else {
+            let index = wallet.guardian_signer_index(&ctx.accounts.co_signer)
+                .ok_or(WalletError::UnauthorizedCoSigner)?;
+            let bit = 1u32 << index;
+            require!(pending.guardian_approvals & bit == 0, WalletError::AlreadyApproved);
+            pending.guardian_approvals |= bit;
+        }
+        
+        emit!(PendingOperationApproved {
+            wallet: pending.wallet,
+            user_op_hash: pending.user_op_hash,
+            co_signer,
+            approvals: pending.approval_count() as u8,
+            required: wallet.required_co_signatures,
+        });
+        
+        Ok(())
+    }
+
+    /// Execute a queued operation once it has enough co-signatures.
+    /// `user_op` must be the operation that was queued.
+    pub fn execute_pending_operation<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecutePendingOperation<'info>>,
+        user_op: UserOperation,
+    ) -> Result<()> {
+        let wallet = ctx.accounts.wallet.load()?;
+        let pending = &ctx.accounts.pending_operation;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        require!(
+            current_time < pending.expires_at,
+            WalletError::PendingOperationExpired
+        );
+        // An owner change since queueing invalidates the operation
+        require!(pending.owner == wallet.owner, WalletError::PendingOperationMismatch);
+        require!(
+            pending.approval_count() >= wallet.required_co_signatures as u32,
+            WalletError::InsufficientCoSignatures
+        );
+        
+        let user_op_hash = user_op.canonical_hash();
+        require!(
+            user_op_hash == pending.user_op_hash && user_op.nonce == pending.nonce,
+            WalletError::PendingOperationMismatch
+        );
+        
+        let calls = decode_calls(&user_op.call_data)?;
+        drop(wallet);
+        
+        run_user_operation(
+            &ctx.accounts.wallet,
+            ctx.remaining_accounts,
+            &user_op_hash,
+            user_op.nonce,
+            &calls,
+            pending.value,
+            None,
+            current_time,
+        )
+    }
+
+    /// Drop a queued operation. The owner or any guardian may cancel at any
+    /// time; anyone may clean up once the co-signing window has passed.
+    pub fn cancel_pending_operation(
+        ctx: Context<CancelPendingOperation>,
+    ) -> Result<()> {
+        let wallet = ctx.accounts.wallet.load()?;
+        let pending = &ctx.accounts.pending_operation;
+        let authority = ctx.accounts.authority.key();
+        
+        let expired = Clock::get()?.unix_timestamp >= pending.expires_at;
+        let is_guardian = wallet
+            .guardian_signer_index(&ctx.accounts.authority)
+            .is_some();
+        require!(
+            expired || authority == wallet.owner || is_guardian,
+            WalletError::UnauthorizedCoSigner
+        );
+        
+        emit!(PendingOperationCancelled {
+            wallet: pending.wallet,
+            user_op_hash: pending.user_op_hash,
+            cancelled_by: authority,
+        });
+        
+        Ok(())
+    }
+
+    /// Create a sub-wallet controlled by this wallet and operated by `owner`
+    pub fn create_sub_wallet(
+        ctx: Context<CreateSubWallet>,
+        index: u32,
+        owner: Pubkey,
+        budget_per_period: u64,
+        period: i64,
+    ) -> Result<()> {
+        require!(period > 0, WalletError::InvalidBudgetPeriod);
+        
+        let wallet_key = ctx.accounts.wallet.key();
+        record_owner_activity(&mut *ctx.accounts.wallet.load_mut()?, wallet_key)?;
+        
+        let sub_wallet = &mut ctx.accounts.sub_wallet;
+        
+        sub_wallet.parent = wallet_key;
+        sub_wallet.owner = owner;
+        sub_wallet.index = index;
+        sub_wallet.budget_per_period = budget_per_period;
+        sub_wallet.period = period;
+        sub_wallet.spent_in_period = 0;
+        sub_wallet.period_start = Clock::get()?.unix_timestamp;
+        sub_wallet.is_frozen = false;
+        sub_wallet.bump = *ctx.bumps.get("sub_wallet").ok_or(WalletError::InvalidWalletAccount)?;
+        
+        emit!(SubWalletCreated {
+            wallet: wallet_key,
+            sub_wallet: sub_wallet.key(),
+            owner,
+            index,
+            budget_per_period,
+            period,
+        });
+        
+        Ok(())
+    }
+
+    /// Move lamports from the parent wallet into a sub-wallet. The amount
+    /// is charged to the parent's spending windows (price feeds for USD
+    /// windows go in remaining accounts) and counts towards its co-signing
+    /// threshold.
+    ///
+    /// Funding that needs co-signers is queued instead, like a user
+    /// operation: as `sub_wallet_funding_operation` under the wallet's next
+    /// nonce, which requires the optional `pending_operation`, `payer` and
+    /// `system_program` accounts. Once co-signed it runs through
+    /// `execute_pending_operation`.
+    pub fn fund_sub_wallet<'info>(
+        ctx: Context<'_, '_, '_, 'info, FundSubWallet<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let sub_wallet_key = ctx.accounts.sub_wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        if wallet.requires_co_signing(&[], amount, current_time) {
+            let user_op = sub_wallet_funding_operation(wallet_key, sub_wallet_key, amount, wallet.nonce)?;
+            let user_op_hash = user_op.canonical_hash();
+            let owner = wallet.owner;
+            let expires_at = current_time + wallet.co_sign_window;
+            wallet.nonce += 1;
+            drop(wallet);
+            
+            queue_pending_operation(
+                ctx.accounts.pending_operation.as_ref(),
+                ctx.accounts.payer.as_ref(),
+                ctx.accounts.system_program.as_ref(),
+                PendingOperation {
+                    wallet: wallet_key,
+                    owner,
+                    rent_payer: Pubkey::default(),
+                    user_op_hash,
+                    nonce: user_op.nonce,
+                    value: amount,
+                    expires_at,
+                    guardian_approvals: 0,
+                    second_factor_approved: false,
+                },
+            )?;
+            
+            emit!(UserOperationQueued {
+                wallet: wallet_key,
+                user_op_hash,
+                nonce: user_op.nonce,
+                value: amount,
+                expires_at,
+            });
+            
+            return Ok(());
+        }
+        
+        wallet.consume_spending(
+            &SpendAmounts { lamports: amount, tokens: Vec::new() },
+            ctx.remaining_accounts,
+            current_time,
+        )?;
+        wallet.record_unsigned_value(amount, current_time);
+        drop(wallet);
+        
+        let wallet_info = ctx.accounts.wallet.to_account_info();
+        let sub_wallet_info = ctx.accounts.sub_wallet.to_account_info();
+        
+        move_lamports(&wallet_info, &sub_wallet_info, amount)?;
+        
+        emit!(SubWalletFunded {
+            wallet: wallet_key,
+            sub_wallet: sub_wallet_info.key(),
+            amount,
+        });
+        
+        Ok(())
+    }
+
+    /// Change a sub-wallet's per-period budget
+    pub fn set_sub_wallet_budget(
+        ctx: Context<ModifySubWallet>,
+        budget_per_period: u64,
+        period: i64,
+    ) -> Result<()> {
+        require!(period > 0, WalletError::InvalidBudgetPeriod);
+        
+        let wallet_key = ctx.accounts.wallet.key();
+        record_owner_activity(&mut *ctx.accounts.wallet.load_mut()?, wallet_key)?;
+        
+        let sub_wallet = &mut ctx.accounts.sub_wallet;
+        
+        sub_wallet.budget_per_period = budget_per_period;
+        if sub_wallet.period != period {
+            sub_wallet.period = period;
+            sub_wallet.spent_in_period = 0;
+            sub_wallet.period_start = Clock::get()?.unix_timestamp;
+        }
+        
+        emit!(SubWalletBudgetUpdated {
+            wallet: wallet_key,
+            sub_wallet: sub_wallet.key(),
+            budget_per_period,
+            period,
+        });
+        
+        Ok(())
+    }
+
+    /// Freeze or unfreeze a sub-wallet
+    pub fn set_sub_wallet_frozen(
+        ctx: Context<ModifySubWallet>,
+        is_frozen: bool,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        record_owner_activity(&mut *ctx.accounts.wallet.load_mut()?, wallet_key)?;
+        
+        let sub_wallet = &mut ctx.accounts.sub_wallet;
+        
+        sub_wallet.is_frozen = is_frozen;
+        
+        emit!(SubWalletStatusChanged {
+            wallet: wallet_key,
+            sub_wallet: sub_wallet.key(),
+            is_frozen,
+        });
+        
+        Ok(())
+    }
+
+    /// Pull lamports back from a sub-wallet into the parent
+    pub fn reclaim_sub_wallet(
+        ctx: Context<ModifySubWallet>,
+        amount: u64,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        record_owner_activity(&mut *ctx.accounts.wallet.load_mut()?, wallet_key)?;
+        
+        let wallet_info = ctx.accounts.wallet.to_account_info();
+        let sub_wallet_info = ctx.accounts.sub_wallet.to_account_info();
+        
+        move_lamports(&sub_wallet_info, &wallet_info, amount)?;
+        
+        emit!(SubWalletReclaimed {
+            wallet: wallet_key,
+            sub_wallet: sub_wallet_info.key(),
+            amount,
+        });
+        
+        Ok(())
+    }
+
+    /// Close a sub-wallet, returning all of its lamports to the parent
+    pub fn close_sub_wallet(
+        ctx: Context<CloseSubWallet>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        record_owner_activity(&mut *ctx.accounts.wallet.load_mut()?, wallet_key)?;
+        
+        emit!(SubWalletClosed {
+            wallet: wallet_key,
+            sub_wallet: ctx.accounts.sub_wallet.key(),
+            amount: ctx.accounts.sub_wallet.to_account_info().lamports(),
+        });
+        
+        Ok(())
+    }
+
+    /// Execute calls from a sub-wallet (signed by the sub-wallet owner).
+    /// Lamports sent by the calls count against the sub-wallet's budget.
+    /// Calls can only be lamport transfers: the budget is in lamports, and
+    /// what a program call signed by the sub-wallet moves could not be
+    /// charged to it.
+    pub fn execute_sub_wallet_calls<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteSubWalletCalls<'info>>,
+        calls: Vec<WalletCall>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.wallet.load()?.is_frozen(), WalletError::WalletFrozen);
+        
+        let sub_wallet = &mut ctx.accounts.sub_wallet;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        require!(!sub_wallet.is_frozen, WalletError::WalletFrozen);
+        require!(
+            calls.iter().all(|call| call.data.is_empty()),
+            WalletError::SubWalletProgramCall
+        );
+        
+        // Reset the budget once the period has passed
+        if current_time - sub_wallet.period_start >= sub_wallet.period {
+            sub_wallet.spent_in_period = 0;
+            sub_wallet.period_start = current_time;
+        }
+        
+        let value = calls_value(&calls)?;
+        let spent = sub_wallet.spent_in_period
+            .checked_add(value)
+            .ok_or(WalletError::BudgetExceeded)?;
+        require!(spent <= sub_wallet.budget_per_period, WalletError::BudgetExceeded);
+        
+        sub_wallet.spent_in_period = spent;
+        
+        let parent = sub_wallet.parent;
+        let index_bytes = sub_wallet.index.to_le_bytes();
+        let seeds = &[
+            b"sub_wallet".as_ref(),
+            parent.as_ref(),
+            index_bytes.as_ref(),
+            &[sub_wallet.bump],
+        ];
+        
+        execute_calls(
+            &sub_wallet.to_account_info(),
+            &calls,
+            ctx.remaining_accounts,
+            &[seeds],
+        )
+        .map_err(|failure| failure.error)?;
+        
+        emit!(SubWalletCallsExecuted {
+            wallet: parent,
+            sub_wallet: sub_wallet.key(),
+            owner: sub_wallet.owner,
+            value,
+            spent_in_period: spent,
+        });
+        
+        Ok(())
+    }
+
+    /// Create or replace the spending window in slot `index`
+    pub fn set_spending_window(
+        ctx: Context<ModifySpendingWindows>,
+        index: u8,
+        limit: u64,
+        duration: i64,
+        mode: SpendingWindowMode,
+        denomination: SpendingDenomination,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!((index as usize) < MAX_SPENDING_WINDOWS, WalletError::InvalidSpendingWindow);
+        validate_window_duration(duration, mode)?;
+        
+        let mut window = SpendingWindow::new(limit, duration, mode, Clock::get()?.unix_timestamp);
+        window.denomination = denomination as u8;
+        wallet.spending_windows[index as usize] = window;
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(SpendingWindowUpdated {
+            wallet: wallet_key,
+            index,
+            limit,
+            duration,
+            mode,
+            denomination,
+        });
+        
+        Ok(())
+    }
+
+    /// Remove the spending window in slot `index`
+    pub fn remove_spending_window(
+        ctx: Context<ModifySpendingWindows>,
+        index: u8,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!((index as usize) < MAX_SPENDING_WINDOWS, WalletError::InvalidSpendingWindow);
+        
+        wallet.spending_windows[index as usize] = SpendingWindow::default();
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(SpendingWindowRemoved {
+            wallet: wallet_key,
+            index,
+        });
+        
+        Ok(())
+    }
+
+    /// Configure the price feed used to value `mint` (or SOL when `None`)
+    /// for USD-denominated spending windows. `feed` is a Pyth price account.
+    pub fn set_price_feed(
+        ctx: Context<ModifySpendingWindows>,
+        index: u8,
+        mint: Option<Pubkey>,
+        feed: Pubkey,
+        decimals: u8,
+        max_staleness: i64,
+        max_confidence_bps: u16,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!((index as usize) < MAX_PRICE_FEEDS, WalletError::InvalidPriceFeed);
+        require!(
+            max_staleness > 0 && max_confidence_bps as u64 <= BPS_DENOMINATOR,
+            WalletError::InvalidPriceFeed
+        );
+        
+        let mint = mint.unwrap_or_default();
+        require!(
+            wallet.price_feeds.iter().enumerate().all(|(i, f)| {
+                i == index as usize || !f.is_active() || f.mint != mint
+            }),
+            WalletError::InvalidPriceFeed
+        );
+        
+        let decimals = if mint == Pubkey::default() { SOL_DECIMALS } else { decimals };
+        wallet.price_feeds[index as usize] = PriceFeedConfig {
+            mint,
+            feed,
+            max_staleness,
+            max_confidence_bps,
+            decimals,
+            is_active: 1,
+            _padding: [0; 4],
+        };
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(PriceFeedUpdated {
+            wallet: wallet_key,
+            index,
+            mint,
+            feed,
+            max_staleness,
+            max_confidence_bps,
+        });
+        
+        Ok(())
+    }
+
+    /// Remove the price feed in slot `index`
+    pub fn remove_price_feed(
+        ctx: Context<ModifySpendingWindows>,
+        index: u8,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        
+        require!((index as usize) < MAX_PRICE_FEEDS, WalletError::InvalidPriceFeed);
+        
+        let mint = wallet.price_feeds[index as usize].mint;
+        wallet.price_feeds[index as usize] = PriceFeedConfig::default();
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        emit!(PriceFeedRemoved {
+            wallet: wallet_key,
+            index,
+            mint,
+        });
+        
+        Ok(())
+    }
+
+    /// Read-only view of every active spending window and its remaining allowance
+    pub fn get_spending_windows(
+        ctx: Context<GetSpendingWindows>,
+    ) -> Result<Vec<SpendingWindowStatus>> {
+        let wallet = ctx.accounts.wallet.load()?;
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        wallet.spending_windows
+            .iter()
+            .enumerate()
+            .filter(|(_, window)| window.is_active())
+            .map(|(index, window)| window.status(index as u8, current_time))
+            .collect()
+    }
+
+    /// Create an address lookup table whose authority is the wallet PDA.
+    /// `recent_slot` must be a recent slot; it seeds the table's address.
+    pub fn create_lookup_table(
+        ctx: Context<CreateLookupTable>,
+        recent_slot: u64,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let (table, bump_seed) = lookup_table_address(&wallet_key, recent_slot);
+        require!(table == ctx.accounts.lookup_table.key(), WalletError::InvalidLookupTable);
+        
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        let initial_owner = wallet.initial_owner;
+        let recovery_hash = wallet.recovery_hash;
+        let bump = wallet.bump;
+        drop(wallet);
+        
+        let seeds = &[
+            b"wallet".as_ref(),
+            initial_owner.as_ref(),
+            recovery_hash.as_ref(),
+            &[bump],
+        ];
+        
+        invoke_signed(
+            &Instruction {
+                program_id: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+                accounts: vec![
+                    AccountMeta::new(table, false),
+                    AccountMeta::new_readonly(wallet_key, true),
+                    AccountMeta::new(ctx.accounts.payer.key(), true),
+                    AccountMeta::new_readonly(System::id(), false),
+                ],
+                data: lookup_table_instruction_data(
+                    LookupTableInstruction::Create { recent_slot, bump_seed },
+                ),
+            },
+            &[
+                ctx.accounts.lookup_table.to_account_info(),
+                ctx.accounts.wallet.to_account_info(),
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.address_lookup_table_program.to_account_info(),
+            ],
+            &[seeds],
+        )?;
+        
+        emit!(LookupTableCreated {
+            wallet: wallet_key,
+            lookup_table: table,
+            recent_slot,
+        });
+        
+        Ok(())
+    }
+
+    /// Append addresses to one of the wallet's lookup tables; `payer`
+    /// covers the extra rent
+    pub fn extend_lookup_table(
+        ctx: Context<ExtendLookupTable>,
+        addresses: Vec<Pubkey>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        require!(!addresses.is_empty(), WalletError::InvalidLookupTable);
+        
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        let initial_owner = wallet.initial_owner;
+        let recovery_hash = wallet.recovery_hash;
+        let bump = wallet.bump;
+        drop(wallet);
+        
+        let seeds = &[
+            b"wallet".as_ref(),
+            initial_owner.as_ref(),
+            recovery_hash.as_ref(),
+            &[bump],
+        ];
+        let table = ctx.accounts.lookup_table.key();
+        let added = addresses.len() as u8;
+        
+        invoke_signed(
+            &Instruction {
+                program_id: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+                accounts: vec![
+                    AccountMeta::new(table, false),
+                    AccountMeta::new_readonly(wallet_key, true),
+                    AccountMeta::new(ctx.accounts.payer.key(), true),
+                    AccountMeta::new_readonly(System::id(), false),
+                ],
+                data: lookup_table_instruction_data(LookupTableInstruction::Extend(addresses)),
+            },
+            &[
+                ctx.accounts.lookup_table.to_account_info(),
+                ctx.accounts.wallet.to_account_info(),
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.address_lookup_table_program.to_account_info(),
+            ],
+            &[seeds],
+        )?;
+        
+        emit!(LookupTableExtended {
+            wallet: wallet_key,
+            lookup_table: table,
+            added,
+        });
+        
+        Ok(())
+    }
+
+    /// Deactivate one of the wallet's lookup tables. It can be closed once
+    /// the deactivation slot is no longer in the slot hashes sysvar.
+    pub fn deactivate_lookup_table(
+        ctx: Context<DeactivateLookupTable>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        let initial_owner = wallet.initial_owner;
+        let recovery_hash = wallet.recovery_hash;
+        let bump = wallet.bump;
+        drop(wallet);
+        
+        let seeds = &[
+            b"wallet".as_ref(),
+            initial_owner.as_ref(),
+            recovery_hash.as_ref(),
+            &[bump],
+        ];
+        let table = ctx.accounts.lookup_table.key();
+        
+        invoke_signed(
+            &Instruction {
+                program_id: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+                accounts: vec![
+                    AccountMeta::new(table, false),
+                    AccountMeta::new_readonly(wallet_key, true),
+                ],
+                data: lookup_table_instruction_data(LookupTableInstruction::Deactivate),
+            },
+            &[
+                ctx.accounts.lookup_table.to_account_info(),
+                ctx.accounts.wallet.to_account_info(),
+                ctx.accounts.address_lookup_table_program.to_account_info(),
+            ],
+            &[seeds],
+        )?;
+        
+        emit!(LookupTableDeactivated {
+            wallet: wallet_key,
+            lookup_table: table,
+        });
+        
+        Ok(())
+    }
+
+    /// Close a deactivated lookup table, returning its rent to `recipient`
+    pub fn close_lookup_table(
+        ctx: Context<CloseLookupTable>,
+    ) -> Result<()> {
+        let wallet_key = ctx.accounts.wallet.key();
+        let mut wallet = ctx.accounts.wallet.load_mut()?;
+        require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+        record_owner_activity(&mut wallet, wallet_key)?;
+        
+        let initial_owner = wallet.initial_owner;
+        let recovery_hash = wallet.recovery_hash;
+        let bump = wallet.bump;
+        drop(wallet);
+        
+        let seeds = &[
+            b"wallet".as_ref(),
+            initial_owner.as_ref(),
+            recovery_hash.as_ref(),
+            &[bump],
+        ];
+        let table = ctx.accounts.lookup_table.key();
+        
+        invoke_signed(
+            &Instruction {
+                program_id: ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+                accounts: vec![
+                    AccountMeta::new(table, false),
+                    AccountMeta::new_readonly(wallet_key, true),
+                    AccountMeta::new(ctx.accounts.recipient.key(), false),
+                ],
+                data: lookup_table_instruction_data(LookupTableInstruction::Close),
+            },
+            &[
+                ctx.accounts.lookup_table.to_account_info(),
+                ctx.accounts.wallet.to_account_info(),
+                ctx.accounts.recipient.to_account_info(),
+                ctx.accounts.address_lookup_table_program.to_account_info(),
+            ],
+            &[seeds],
+        )?;
+        
+        emit!(LookupTableClosed {
+            wallet: wallet_key,
+            lookup_table: table,
+        });
+        
+        Ok(())
+    }
+}
+
+// Account Structures
+
+/// Guardian slots available in a wallet's fixed-size layout
+pub const MAX_GUARDIANS: usize = 32;
+
+/// Zero-copy wallet state.
+///
+/// The wallet is read in place through `AccountLoader` instead of being
+/// Borsh-decoded into an `Account`. The previous layout held `Vec<Pubkey>`
+/// guardians and a nested `Option<RecoveryRequest>` with its own approvals
+/// vector, so every instruction paid to decode ~800 bytes (with heap
+/// allocations for both vectors) on entry and to re-encode them on exit,
+/// whether or not it touched the guardian set. Loading this layout is a
+/// discriminator check and a pointer cast, independent of how many guardian
+/// slots are in use, and the stored `bump` lets seeds constraints use
+/// `create_program_address` instead of a `find_program_address` search.
+/// Recovery approvals are a `u32` bitmap over guardian slots rather than a
+/// vector of keys.
+///
+/// The saving is the Borsh round trip itself: with ten guardians that all
+/// approved a recovery a `LegacyWallet` is 787 bytes, all decoded and
+/// re-encoded by every instruction (see the `legacy_wallets_migrate`
+/// test). Its cost in compute units has not been measured, as host tests
+/// do not meter them: simulate `execute_user_operation` and
+/// `approve_recovery` against an SBF build on a local validator, with a
+/// wallet of each layout, and compare the reported `unitsConsumed`.
+///
+/// Wallets created with the Borsh layout are too small to load as a
+/// `Wallet`, so every instruction fails on them until their owner calls
+/// `migrate_wallet`. That grows the account in place, at the owner's
+/// expense for the extra rent, and converts its state; the address and
+/// funds stay where they are.
+#[account(zero_copy)]
+pub struct Wallet {
+    pub owner: Pubkey,                          // 32
+    pub initial_owner: Pubkey,                  // 32 (PDA seed, stable across owner changes)
+    pub recovery_hash: [u8; 32],                // 32
+    pub guardians: [Pubkey; MAX_GUARDIANS],     // 32 * 32 = 1024
+    pub guardian_attestors: [Pubkey; MAX_GUARDIANS], // 32 * 32 = 1024 (verifier PDA per slot, default otherwise)
+    pub pending_recovery: RecoveryRequest,      // 48
+    pub inheritance: InheritanceConfig,         // 64
+    pub pending_owner_rotation: OwnerRotation,  // 48
+    pub second_factor: Pubkey,                  // 32 (default when unset)
+    pub vault: Pubkey,                          // 32 (default when unset)
+    pub pending_vault: Pubkey,                  // 32 (default when nothing is pending)
+    pub aggregator: Pubkey,                     // 32 (signature aggregator, default when unset)
+    pub spending_windows: [SpendingWindow; MAX_SPENDING_WINDOWS], // 4 * 48 = 192
+    pub price_feeds: [PriceFeedConfig; MAX_PRICE_FEEDS], // 4 * 80 = 320
+    pub nonce: u64,                             // 8
+    pub last_activity: i64,                     // 8
+    pub owner_rotation_delay: i64,              // 8
+    pub co_sign_threshold: u64,                 // 8 (lamports, 0 = disabled)
+    pub co_sign_window: i64,                    // 8
+    pub vault_change_at: i64,                   // 8
+    pub co_sign_period_start: i64,              // 8
+    pub co_sign_period_value: u64,              // 8 (lamports sent without co-signing this period)
+    pub pending_rotation_delay: i64,            // 8 (scheduled decrease of owner_rotation_delay)
+    pub rotation_delay_change_at: i64,          // 8 (0 when no decrease is scheduled)
+    pub frozen_at: i64,                         // 8 (time the wallet was frozen)
+    pub guardian_kinds: [u8; MAX_GUARDIANS],    // 32 (GuardianKind per guardian slot)
+    pub vault_sweep_approvals: u32,             // 4 (bitmap over guardian slots)
+    pub guardian_count: u8,                     // 1
+    pub initialized: u8,                        // 1
+    pub is_frozen: u8,                          // 1
+    pub bump: u8,                               // 1
+    pub required_co_signatures: u8,             // 1
+    pub co_sign_program_calls: u8,              // 1 (queue every program call while co-signing)
+    pub _padding: [u8; 6],                      // 6
+}
+
+/// How a guardian slot proves approval
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuardianKind {
+    /// The stored key signs directly
+    Key,
+    /// The stored key is another nexus `Wallet` PDA, which signs by CPI from
+    /// one of its own user operations
+    Wallet,
+    /// The stored key is a verifier program (e.g. an email-OTP service)
+    /// that attests approval by CPI, signing with its PDA
+    /// `[GUARDIAN_VERIFIER_SEED, wallet]`
+    Verifier,
+}
+
+#[zero_copy]
+#[derive(Default)]
+pub struct RecoveryRequest {
+    pub new_owner: Pubkey,                      // 32
+    pub initiated_at: i64,                      // 8
+    pub guardian_approvals: u32,                // 4 (bitmap over guardian slots)
+    pub is_active: u8,                          // 1
+    pub _padding: [u8; 3],                      // 3
+}
+
+#[zero_copy]
+#[derive(Default)]
+pub struct InheritanceConfig {
+    pub beneficiary: Pubkey,                    // 32
+    pub inactivity_period: i64,                 // 8
+    pub grace_period: i64,                      // 8
+    pub claim_started_at: i64,                  // 8 (0 when no claim is pending)
+    pub mode: u8,                               // 1 (InheritanceMode)
+    pub is_configured: u8,                      // 1
+    pub _padding: [u8; 6],                      // 6
+}
+
+/// A spending limit enforced over one period. Rolling windows keep the
+/// previous period's total and weight it by how much of that period still
+/// overlaps the trailing window, approximating a true sliding window.
+#[zero_copy]
+#[derive(Default)]
+pub struct SpendingWindow {
+    pub limit: u64,                             // 8 (lamports)
+    pub spent: u64,                             // 8 (current period)
+    pub previous_spent: u64,                    // 8 (rolling mode only)
+    pub duration: i64,                          // 8
+    pub window_start: i64,                      // 8
+    pub mode: u8,                               // 1 (SpendingWindowMode)
+    pub is_active: u8,                          // 1
+    pub denomination: u8,                       // 1 (SpendingDenomination)
+    pub _padding: [u8; 5],                      // 5
+}
+
+/// Price feed used to value one asset for USD-denominated windows
+#[zero_copy]
+#[derive(Default)]
+pub struct PriceFeedConfig {
+    pub mint: Pubkey,                           // 32 (default for native SOL)
+    pub feed: Pubkey,                           // 32
+    pub max_staleness: i64,                     // 8
+    pub max_confidence_bps: u16,                // 2
+    pub decimals: u8,                           // 1
+    pub is_active: u8,                          // 1
+    pub _padding: [u8; 4],                      // 4
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpendingDenomination {
+    /// Limit is in lamports; only SOL leaving the wallet is counted
+    Lamports,
+    /// Limit is in micro-USD (6 decimals); SOL and token outflows are priced
+    /// through the wallet's configured price feeds
+    Usd,
+}
+
+/// Aggregate price read from a Pyth v2 price account. Only the fields the
+/// wallet needs are decoded (offsets as in `pyth-sdk-solana`'s
+/// `PriceAccount`, all little-endian):
+///
+/// | offset | field          | type     |
+/// |--------|----------------|----------|
+/// | 0      | magic          | u32      |
+/// | 4      | version        | u32      |
+/// | 8      | account type   | u32      |
+/// | 20     | expo           | i32      |
+/// | 96     | timestamp      | i64      |
+/// | 208    | agg.price      | i64      |
+/// | 216    | agg.conf       | u64      |
+/// | 224    | agg.status     | u32      |
+///
+/// The account must be owned by `PYTH_ORACLE_PROGRAM_ID` and its aggregate
+/// must be trading; staleness is checked against the feed's configured
+/// `max_staleness` when the price is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriceFeedData {
+    pub expo: i32,
+    pub price: i64,
+    pub conf: u64,
+    pub publish_time: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpendingWindowMode {
+    /// Trailing window ending at the current time, at most
+    /// `MAX_ROLLING_WINDOW` long
+    Rolling,
+    /// Fixed UTC periods: hours, days, weeks starting Monday, or calendar
+    /// months (`duration` of `SECONDS_PER_MONTH`)
+    CalendarAligned,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SpendingWindowStatus {
+    pub index: u8,
+    pub limit: u64,
+    pub duration: i64,
+    pub mode: SpendingWindowMode,
+    pub denomination: SpendingDenomination,
+    pub used: u64,
+    pub remaining: u64,
+    /// When the current period ends (calendar windows) or when the oldest
+    /// counted spending fully drops out (rolling windows)
+    pub resets_at: i64,
+}
+
+#[zero_copy]
+#[derive(Default)]
+pub struct OwnerRotation {
+    pub new_owner: Pubkey,                      // 32 (default when nothing is pending)
+    pub proposed_at: i64,                       // 8
+    pub executable_at: i64,                     // 8
+}
+
+/// Borsh layout of wallets created before the zero-copy `Wallet`, read
+/// once by `migrate_wallet`. The discriminator is the same as `Wallet`'s.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyWallet {
+    pub owner: Pubkey,
+    pub recovery_hash: [u8; 32],
+    pub daily_limit: u64,
+    pub daily_spent: u64,
+    pub last_reset: i64,
+    pub nonce: u64,
+    pub initialized: bool,
+    pub is_frozen: bool,
+    pub guardians: Vec<Pubkey>,
+    pub pending_recovery: Option<LegacyRecoveryRequest>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyRecoveryRequest {
+    pub new_owner: Pubkey,
+    pub guardian_approvals: Vec<Pubkey>,
+    pub initiated_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InheritanceMode {
+    /// Beneficiary becomes the wallet owner
+    TransferOwnership,
+    /// Lamports above rent and the passed token balances go to the beneficiary
+    SweepAssets,
+}
+
+/// Child wallet funded and capped by a parent `Wallet`
+#[account]
+#[derive(InitSpace)]
+pub struct SubWallet {
+    pub parent: Pubkey,                   // 32
+    pub owner: Pubkey,                    // 32
+    pub index: u32,                       // 4
+    pub budget_per_period: u64,           // 8 (lamports)
+    pub period: i64,                      // 8
+    pub spent_in_period: u64,             // 8
+    pub period_start: i64,                // 8
+    pub is_frozen: bool,                  // 1
+    pub bump: u8,                         // 1
+}
+
+/// High-value operation waiting for co-signatures
+#[account]
+#[derive(InitSpace)]
+pub struct PendingOperation {
+    pub wallet: Pubkey,                   // 32
+    pub owner: Pubkey,                    // 32 (owner at queue time)
+    pub rent_payer: Pubkey,               // 32
+    pub user_op_hash: [u8; 32],           // 32
+    pub nonce: u64,                       // 8
+    pub value: u64,                       // 8
+    pub expires_at: i64,                  // 8
+    pub guardian_approvals: u32,          // 4 (bitmap over guardian slots)
+    pub second_factor_approved: bool,     // 1
+}
+
+/// Delegated spending right for one (wallet, spender, mint) triple.
+/// The spender may be a plain key or a PDA signer of a dapp program.
+#[account]
+#[derive(InitSpace)]
+pub struct Allowance {
+    pub wallet: Pubkey,                   // 32
+    pub spender: Pubkey,                  // 32
+    pub mint: Pubkey,                     // 32
+    pub amount: u64,                      // 8 (remaining)
+    pub expires_at: Option<i64>,          // 1 + 8
+}
+
+/// A single call made by the wallet, encoded as `Vec<WalletCall>` in
+/// `UserOperation.call_data`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct WalletCall {
+    /// Program to invoke, or the recipient when `data` is empty
+    pub target: Pubkey,
+    pub accounts: Vec<WalletCallAccount>,
+    pub data: Vec<u8>,
+    /// Lamports sent from the wallet to `target` before the call
+    pub value: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct WalletCallAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// Result of `simulate_user_operation`, returned through return data
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SimulationReport {
+    pub user_op_hash: [u8; 32],
+    /// Key whose signature authorized the operation
+    pub signer: Pubkey,
+    /// Whether executing for real would queue the operation for co-signing,
+    /// or fail with `CoSigningRequired` for the tokens its calls moved
+    pub requires_co_signing: bool,
+    pub limits: Vec<LimitConsumption>,
+    pub call_results: Vec<CallResult>,
+    /// Balance changes of the wallet and the passed accounts; unchanged
+    /// accounts are omitted
+    pub balance_deltas: Vec<BalanceDelta>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LimitConsumption {
+    pub index: u8,
+    pub denomination: SpendingDenomination,
+    pub charged: u64,
+    pub remaining: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CallResult {
+    pub target: Pubkey,
+    pub value: u64,
+    /// Net change of the wallet's token balance per mint during the call,
+    /// over the token accounts passed in `remaining_accounts`
+    pub token_deltas: Vec<(Pubkey, i128)>,
+    /// Compute units consumed by the invocation of `target`
+    pub compute_units: u64,
+    /// Return data set by `target`, if any
+    pub return_data: Vec<u8>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct BalanceDelta {
+    pub account: Pubkey,
+    pub lamports: i128,
+    /// Mint and change in amount when the account is an SPL token account
+    pub token: Option<(Pubkey, i128)>,
+}
+
+/// Result of `validate_user_op`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct UserOpValidation {
+    pub user_op_hash: [u8; 32],
+    pub status: UserOpStatus,
+    /// Aggregator that must verify the signature, if the wallet uses one
+    pub aggregator: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserOpStatus {
+    Valid,
+    /// `sender` is not this wallet
+    WrongSender,
+    WalletFrozen,
+    InvalidNonce,
+    InvalidSignature,
+    InvalidCallData,
+    /// The operation's value exceeds the co-signing threshold, so executing
+    /// it would only queue it
+    RequiresCoSigning,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PaymasterData {
+    pub paymaster: Pubkey,
+    pub token_mint: Option<Pubkey>,
+    pub max_cost: u64,
+    pub signature: [u8; 64],
+}
+
+// Context Structures
+#[derive(Accounts)]
+#[instruction(owner: Pubkey, recovery_hash: [u8; 32], daily_limit: u64)]
+pub struct InitializeWallet<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + std::mem::size_of::<Wallet>(),
+        seeds = [b"wallet", owner.as_ref(), &recovery_hash],
+        bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateWallet<'info> {
+    /// CHECK: A `LegacyWallet`, decoded and checked against its PDA in
+    /// `migrate_wallet`
+    #[account(mut, owner = crate::ID)]
+    pub wallet: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteUserOperation<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    /// CHECK: Created as a `PendingOperation` PDA when the op needs co-signers
+    #[account(mut)]
+    pub pending_operation: Option<UncheckedAccount<'info>>,
+    
+    #[account(mut)]
+    pub payer: Option<Signer<'info>>,
+    
+    pub system_program: Option<Program<'info, System>>,
+    
+    /// Required with `PaymasterData`: the sponsoring paymaster, the account
+    /// holding its payment method and its program
+    #[account(mut)]
+    pub paymaster: Option<Account<'info, Paymaster>>,
+    
+    /// CHECK: Read by the paymaster program
+    pub paymaster_data_account: Option<UncheckedAccount<'info>>,
+    
+    pub paymaster_program: Option<Program<'info, NexusPaymaster>>,
+    
+    /// CHECK: Instructions sysvar, holding the ed25519 signatures of the
+    /// owner and of the paymaster's owner
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyGuardians<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitiateRecovery<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub guardian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveRecovery<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub guardian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FreezeWallet<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub guardian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UnfreezeWallet<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(spender: Pubkey)]
+pub struct ApproveAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + Allowance::INIT_SPACE,
+        seeds = [b"allowance", wallet.key().as_ref(), spender.as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"allowance", wallet.key().as_ref(), allowance.spender.as_ref(), allowance.mint.as_ref()],
+        bump,
+        has_one = wallet,
+        close = owner
+    )]
+    pub allowance: Account<'info, Allowance>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SpendAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"allowance", wallet.key().as_ref(), spender.key().as_ref(), allowance.mint.as_ref()],
+        bump,
+        has_one = wallet,
+        has_one = spender
+    )]
+    pub allowance: Account<'info, Allowance>,
+    
+    pub spender: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = source_token_account.owner == wallet.key() @ WalletError::InvalidTokenAccount,
+        constraint = source_token_account.mint == allowance.mint @ WalletError::InvalidTokenAccount
+    )]
+    pub source_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = destination_token_account.mint == allowance.mint @ WalletError::InvalidTokenAccount
+    )]
+    pub destination_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureInheritance<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InheritanceClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub beneficiary: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeInheritanceClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyOwnerRotation<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOwnership<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub new_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateLookupTable<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    /// CHECK: Derived from the wallet and `recent_slot` in the handler
+    #[account(mut)]
+    pub lookup_table: UncheckedAccount<'info>,
+    
+    /// CHECK: Address lookup table program
+    #[account(address = ADDRESS_LOOKUP_TABLE_PROGRAM_ID)]
+    pub address_lookup_table_program: UncheckedAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendLookupTable<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    /// CHECK: The lookup table program checks the wallet is its authority
+    #[account(mut, owner = ADDRESS_LOOKUP_TABLE_PROGRAM_ID)]
+    pub lookup_table: UncheckedAccount<'info>,
+    
+    /// CHECK: Address lookup table program
+    #[account(address = ADDRESS_LOOKUP_TABLE_PROGRAM_ID)]
+    pub address_lookup_table_program: UncheckedAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DeactivateLookupTable<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+    
+    /// CHECK: The lookup table program checks the wallet is its authority
+    #[account(mut, owner = ADDRESS_LOOKUP_TABLE_PROGRAM_ID)]
+    pub lookup_table: UncheckedAccount<'info>,
+    
+    /// CHECK: Address lookup table program
+    #[account(address = ADDRESS_LOOKUP_TABLE_PROGRAM_ID)]
+    pub address_lookup_table_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLookupTable<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+    
+    /// CHECK: The lookup table program checks the wallet is its authority
+    #[account(mut, owner = ADDRESS_LOOKUP_TABLE_PROGRAM_ID)]
+    pub lookup_table: UncheckedAccount<'info>,
+    
+    /// CHECK: Receives the table's rent
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+    
+    /// CHECK: Address lookup table program
+    #[account(address = ADDRESS_LOOKUP_TABLE_PROGRAM_ID)]
+    pub address_lookup_table_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyVaultChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+}
+
+#[derive(Accounts)]
+pub struct CancelVaultChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveVaultSweep<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub guardian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SweepToVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    /// CHECK: must be the wallet's registered vault
+    #[account(mut, address = wallet.load()?.vault)]
+    pub vault: UncheckedAccount<'info>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOwnerRotation<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u32)]
+pub struct CreateSubWallet<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + SubWallet::INIT_SPACE,
+        seeds = [b"sub_wallet", wallet.key().as_ref(), &index.to_le_bytes()],
         bump
     )]
-    pub wallet: Account<'info, Wallet>,
+    pub sub_wallet: Account<'info, SubWallet>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ModifySubWallet<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"sub_wallet", wallet.key().as_ref(), &sub_wallet.index.to_le_bytes()],
+        bump = sub_wallet.bump,
+        constraint = sub_wallet.parent == wallet.key() @ WalletError::InvalidWalletAccount
+    )]
+    pub sub_wallet: Account<'info, SubWallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundSubWallet<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"sub_wallet", wallet.key().as_ref(), &sub_wallet.index.to_le_bytes()],
+        bump = sub_wallet.bump,
+        constraint = sub_wallet.parent == wallet.key() @ WalletError::InvalidWalletAccount
+    )]
+    pub sub_wallet: Account<'info, SubWallet>,
+    
+    pub owner: Signer<'info>,
+    
+    /// CHECK: Created as a `PendingOperation` PDA when funding needs co-signers
+    #[account(mut)]
+    pub pending_operation: Option<UncheckedAccount<'info>>,
+    
+    #[account(mut)]
+    pub payer: Option<Signer<'info>>,
+    
+    pub system_program: Option<Program<'info, System>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSubWallet<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"sub_wallet", wallet.key().as_ref(), &sub_wallet.index.to_le_bytes()],
+        bump = sub_wallet.bump,
+        constraint = sub_wallet.parent == wallet.key() @ WalletError::InvalidWalletAccount,
+        close = wallet
+    )]
+    pub sub_wallet: Account<'info, SubWallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSubWalletCalls<'info> {
+    #[account(
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"sub_wallet", wallet.key().as_ref(), &sub_wallet.index.to_le_bytes()],
+        bump = sub_wallet.bump,
+        constraint = sub_wallet.parent == wallet.key() @ WalletError::InvalidWalletAccount,
+        has_one = owner
+    )]
+    pub sub_wallet: Account<'info, SubWallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ModifySpendingWindows<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteAggregatedUserOperation<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    /// The entry point's `EntryPoint` PDA
+    #[account(seeds = [b"entry_point"], bump, seeds::program = ENTRY_POINT_PROGRAM_ID)]
+    pub entry_point: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyAggregator<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ValidateUserOp<'info> {
+    #[account(
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    /// CHECK: Instructions sysvar, holding the owner's ed25519 signature
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetSpendingWindows<'info> {
+    #[account(
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyCoSigning<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump,
+        has_one = owner
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApprovePendingOperation<'info> {
+    #[account(
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"pending_op", wallet.key().as_ref(), &pending_operation.nonce.to_le_bytes()],
+        bump,
+        has_one = wallet
+    )]
+    pub pending_operation: Account<'info, PendingOperation>,
+    
+    pub co_signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePendingOperation<'info> {
+    #[account(
+        mut,
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"pending_op", wallet.key().as_ref(), &pending_operation.nonce.to_le_bytes()],
+        bump,
+        has_one = wallet,
+        has_one = rent_payer,
+        close = rent_payer
+    )]
+    pub pending_operation: Account<'info, PendingOperation>,
+    
+    /// CHECK: Receives the pending operation's rent
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPendingOperation<'info> {
+    #[account(
+        seeds = [b"wallet", wallet.load()?.initial_owner.as_ref(), &wallet.load()?.recovery_hash],
+        bump = wallet.load()?.bump
+    )]
+    pub wallet: AccountLoader<'info, Wallet>,
+    
+    #[account(
+        mut,
+        seeds = [b"pending_op", wallet.key().as_ref(), &pending_operation.nonce.to_le_bytes()],
+        bump,
+        has_one = wallet,
+        has_one = rent_payer,
+        close = rent_payer
+    )]
+    pub pending_operation: Account<'info, PendingOperation>,
+    
+    /// CHECK: Receives the pending operation's rent
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+    
+    pub authority: Signer<'info>,
+}
+
+// Events
+#[event]
+pub struct WalletMigrated {
+    pub wallet: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct WalletInitialized {
+    pub wallet: Pubkey,
+    pub owner: Pubkey,
+    pub daily_limit: u64,
+}
+
+/// Emitted once all of an operation's calls have succeeded; failures are
+/// reported through `UserOperationFailed` instead
+#[event]
+pub struct UserOperationExecuted {
+    pub wallet: Pubkey,
+    pub user_op_hash: [u8; 32],
+    pub nonce: u64,
+    pub calls: u8,
+    /// Lamports charged to spending windows (call value plus self-paid fee)
+    pub lamports_spent: u64,
+    /// Amount of each mint that left the wallet's token accounts
+    pub token_outflows: Vec<(Pubkey, u64)>,
+}
+
+/// One inner call of an executed user operation, emitted in call order
+/// before `UserOperationExecuted`
+#[event]
+pub struct UserOperationCallExecuted {
+    pub wallet: Pubkey,
+    pub user_op_hash: [u8; 32],
+    pub index: u8,
+    pub target: Pubkey,
+    pub lamports: u64,
+    pub token_deltas: Vec<(Pubkey, i128)>,
+    pub compute_units: u64,
+}
+
+/// A user operation rejected by the wallet during execution, e.g. a call
+/// referencing a missing account or a spending limit exceeded by what the
+/// calls moved. The transaction still fails, but the event stays in its
+/// logs. A call whose program itself fails aborts the transaction before
+/// the wallet regains control; its error is only in the runtime logs.
+#[event]
+pub struct UserOperationFailed {
+    pub wallet: Pubkey,
+    pub user_op_hash: [u8; 32],
+    pub nonce: u64,
+    /// Index of the call being run, if the failure happened during a call
+    pub failed_call: Option<u8>,
+    /// Error message, truncated to `MAX_REVERT_REASON_LEN` bytes
+    pub reason: String,
+}
+
+#[event]
+pub struct GuardianAdded {
+    pub wallet: Pubkey,
+    pub guardian: Pubkey,
+    pub kind: GuardianKind,
+}
+
+#[event]
+pub struct GuardianRemoved {
+    pub wallet: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct RecoveryInitiated {
+    pub wallet: Pubkey,
+    pub new_owner: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct RecoveryApproved {
+    pub wallet: Pubkey,
+    pub guardian: Pubkey,
+    pub approvals: u8,
+    pub required: u8,
+}
+
+#[event]
+pub struct RecoveryCompleted {
+    pub wallet: Pubkey,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct WalletFrozen {
+    pub wallet: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct WalletUnfrozen {
+    pub wallet: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct AllowanceApproved {
+    pub wallet: Pubkey,
+    pub spender: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub expires_at: Option<i64>,
+}
+
+#[event]
+pub struct AllowanceRevoked {
+    pub wallet: Pubkey,
+    pub spender: Pubkey,
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct AllowanceSpent {
+    pub wallet: Pubkey,
+    pub spender: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct InheritanceConfigured {
+    pub wallet: Pubkey,
+    pub beneficiary: Pubkey,
+    pub inactivity_period: i64,
+    pub grace_period: i64,
+    pub mode: InheritanceMode,
+}
+
+#[event]
+pub struct InheritanceCleared {
+    pub wallet: Pubkey,
+}
+
+#[event]
+pub struct InheritanceClaimStarted {
+    pub wallet: Pubkey,
+    pub beneficiary: Pubkey,
+    pub claimable_at: i64,
+}
+
+#[event]
+pub struct InheritanceClaimCancelled {
+    pub wallet: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct InheritanceClaimed {
+    pub wallet: Pubkey,
+    pub old_owner: Pubkey,
+    pub beneficiary: Pubkey,
+    pub mode: InheritanceMode,
+}
+
+#[event]
+pub struct OwnerRotationDelayUpdated {
+    pub wallet: Pubkey,
+    pub old_delay: i64,
+    pub new_delay: i64,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct OwnerRotationProposed {
+    pub wallet: Pubkey,
+    pub current_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub executable_at: i64,
+    pub guardians: Vec<Pubkey>,
+}
+
+#[event]
+pub struct OwnerRotationCancelled {
+    pub wallet: Pubkey,
+    pub new_owner: Pubkey,
+    pub cancelled_by: Pubkey,
+}
+
+#[event]
+pub struct LookupTableCreated {
+    pub wallet: Pubkey,
+    pub lookup_table: Pubkey,
+    pub recent_slot: u64,
+}
+
+#[event]
+pub struct LookupTableExtended {
+    pub wallet: Pubkey,
+    pub lookup_table: Pubkey,
+    pub added: u8,
+}
+
+#[event]
+pub struct LookupTableDeactivated {
+    pub wallet: Pubkey,
+    pub lookup_table: Pubkey,
+}
+
+#[event]
+pub struct LookupTableClosed {
+    pub wallet: Pubkey,
+    pub lookup_table: Pubkey,
+}
+
+#[event]
+pub struct VaultChangeProposed {
+    pub wallet: Pubkey,
+    pub current_vault: Pubkey,
+    pub new_vault: Pubkey,
+    pub executable_at: i64,
+    pub guardians: Vec<Pubkey>,
+}
+
+#[event]
+pub struct VaultChanged {
+    pub wallet: Pubkey,
+    pub old_vault: Pubkey,
+    pub new_vault: Pubkey,
+}
+
+#[event]
+pub struct VaultChangeCancelled {
+    pub wallet: Pubkey,
+    pub cancelled_vault: Pubkey,
+    pub cancelled_by: Pubkey,
+}
+
+#[event]
+pub struct VaultSweepApproved {
+    pub wallet: Pubkey,
+    pub guardian: Pubkey,
+    pub approvals: u8,
+    pub required: u8,
+}
+
+#[event]
+pub struct VaultSwept {
+    pub wallet: Pubkey,
+    pub vault: Pubkey,
+    pub lamports: u64,
+    pub token_accounts: u8,
+}
+
+#[event]
+pub struct OwnershipTransferred {
+    pub wallet: Pubkey,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct AggregatorChanged {
+    pub wallet: Pubkey,
+    pub previous: Option<Pubkey>,
+    pub aggregator: Option<Pubkey>,
+}
+
+#[event]
+pub struct CoSigningConfigured {
+    pub wallet: Pubkey,
+    pub threshold: u64,
+    pub window: i64,
+    pub required_co_signatures: u8,
+    pub second_factor: Option<Pubkey>,
+    pub co_sign_program_calls: bool,
+}
+
+#[event]
+pub struct UserOperationQueued {
+    pub wallet: Pubkey,
+    pub user_op_hash: [u8; 32],
+    pub nonce: u64,
+    pub value: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct PendingOperationApproved {
+    pub wallet: Pubkey,
+    pub user_op_hash: [u8; 32],
+    pub co_signer: Pubkey,
+    pub approvals: u8,
+    pub required: u8,
+}
+
+#[event]
+pub struct PendingOperationCancelled {
+    pub wallet: Pubkey,
+    pub user_op_hash: [u8; 32],
+    pub cancelled_by: Pubkey,
+}
+
+#[event]
+pub struct SubWalletCreated {
+    pub wallet: Pubkey,
+    pub sub_wallet: Pubkey,
+    pub owner: Pubkey,
+    pub index: u32,
+    pub budget_per_period: u64,
+    pub period: i64,
+}
+
+#[event]
+pub struct SubWalletFunded {
+    pub wallet: Pubkey,
+    pub sub_wallet: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SubWalletBudgetUpdated {
+    pub wallet: Pubkey,
+    pub sub_wallet: Pubkey,
+    pub budget_per_period: u64,
+    pub period: i64,
+}
+
+#[event]
+pub struct SubWalletStatusChanged {
+    pub wallet: Pubkey,
+    pub sub_wallet: Pubkey,
+    pub is_frozen: bool,
+}
+
+#[event]
+pub struct SubWalletReclaimed {
+    pub wallet: Pubkey,
+    pub sub_wallet: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SubWalletClosed {
+    pub wallet: Pubkey,
+    pub sub_wallet: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SubWalletCallsExecuted {
+    pub wallet: Pubkey,
+    pub sub_wallet: Pubkey,
+    pub owner: Pubkey,
+    pub value: u64,
+    pub spent_in_period: u64,
+}
+
+#[event]
+pub struct SpendingWindowUpdated {
+    pub wallet: Pubkey,
+    pub index: u8,
+    pub limit: u64,
+    pub duration: i64,
+    pub mode: SpendingWindowMode,
+    pub denomination: SpendingDenomination,
+}
+
+#[event]
+pub struct SpendingWindowRemoved {
+    pub wallet: Pubkey,
+    pub index: u8,
+}
+
+#[event]
+pub struct PriceFeedUpdated {
+    pub wallet: Pubkey,
+    pub index: u8,
+    pub mint: Pubkey,
+    pub feed: Pubkey,
+    pub max_staleness: i64,
+    pub max_confidence_bps: u16,
+}
+
+#[event]
+pub struct PriceFeedRemoved {
+    pub wallet: Pubkey,
+    pub index: u8,
+    pub mint: Pubkey,
+}
+
+// Error Definitions
+#[error_code]
+pub enum WalletError {
+    #[msg("Wallet is frozen")]
+    WalletFrozen,
+    #[msg("Invalid nonce")]
+    InvalidNonce,
+    #[msg("Spending limit exceeded")]
+    DailyLimitExceeded,
+    #[msg("Invalid signature")]
+    InvalidSignature,
+    #[msg("Too many guardians")]
+    TooManyGuardians,
+    #[msg("Guardian already exists")]
+    GuardianAlreadyExists,
+    #[msg("Guardian not found")]
+    GuardianNotFound,
+    #[msg("Unauthorized guardian")]
+    UnauthorizedGuardian,
+    #[msg("Recovery already in progress")]
+    RecoveryInProgress,
+    #[msg("No recovery in progress")]
+    NoRecoveryInProgress,
+    #[msg("Already approved recovery")]
+    AlreadyApproved,
+    #[msg("Insufficient gas")]
+    InsufficientGas,
+    #[msg("Allowance expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Allowance has expired")]
+    AllowanceExpired,
+    #[msg("Allowance exceeded")]
+    AllowanceExceeded,
+    #[msg("Invalid token account")]
+    InvalidTokenAccount,
+    #[msg("Invalid inheritance period")]
+    InvalidInheritancePeriod,
+    #[msg("Invalid beneficiary")]
+    InvalidBeneficiary,
+    #[msg("Inheritance not configured")]
+    InheritanceNotConfigured,
+    #[msg("Inheritance claim already in progress")]
+    InheritanceClaimInProgress,
+    #[msg("No inheritance claim in progress")]
+    NoInheritanceClaim,
+    #[msg("Owner has been active within the inactivity period")]
+    OwnerStillActive,
+    #[msg("Inheritance grace period has not elapsed")]
+    GracePeriodNotElapsed,
+    #[msg("Missing token program")]
+    MissingTokenProgram,
+    #[msg("Invalid wallet account")]
+    InvalidWalletAccount,
+    #[msg("Invalid owner rotation delay")]
+    InvalidRotationDelay,
+    #[msg("Invalid new owner")]
+    InvalidNewOwner,
+    #[msg("No owner rotation pending")]
+    NoOwnerRotation,
+    #[msg("Owner rotation delay has not elapsed")]
+    RotationDelayNotElapsed,
+    #[msg("Invalid call data")]
+    InvalidCallData,
+    #[msg("Account referenced by a call was not provided")]
+    MissingCallAccount,
+    #[msg("Insufficient funds")]
+    InsufficientFunds,
+    #[msg("Invalid co-signing configuration")]
+    InvalidCoSigningConfig,
+    #[msg("Operation requires co-signing but no pending operation account was provided")]
+    MissingPendingOperation,
+    #[msg("Unauthorized co-signer")]
+    UnauthorizedCoSigner,
+    #[msg("Pending operation has expired")]
+    PendingOperationExpired,
+    #[msg("Not enough co-signatures")]
+    InsufficientCoSignatures,
+    #[msg("User operation does not match the pending operation")]
+    PendingOperationMismatch,
+    #[msg("Invalid budget period")]
+    InvalidBudgetPeriod,
+    #[msg("Sub-wallet budget exceeded")]
+    BudgetExceeded,
+    #[msg("Invalid spending window")]
+    InvalidSpendingWindow,
+    #[msg("Invalid price feed")]
+    InvalidPriceFeed,
+    #[msg("No price feed configured for asset")]
+    MissingPriceFeed,
+    #[msg("Price is stale")]
+    StalePrice,
+    #[msg("Price confidence interval too wide")]
+    PriceConfidenceTooWide,
+    #[msg("Simulation complete; see return data for the report")]
+    SimulationComplete,
+    #[msg("Simulation report exceeds the return data limit")]
+    SimulationReportTooLarge,
+    #[msg("Invalid guardian")]
+    InvalidGuardian,
+    #[msg("Invalid vault")]
+    InvalidVault,
+    #[msg("No vault registered")]
+    NoVault,
+    #[msg("No vault change pending")]
+    NoVaultChange,
+    #[msg("Vault change delay has not elapsed")]
+    VaultChangeDelayNotElapsed,
+    #[msg("Wallet is not frozen")]
+    WalletNotFrozen,
+    #[msg("Not enough guardian approvals")]
+    InsufficientApprovals,
+    #[msg("Paymaster accounts missing")]
+    MissingPaymasterAccounts,
+    #[msg("Paymaster does not match paymaster data")]
+    InvalidPaymaster,
+    #[msg("Fee exceeds the paymaster's authorized cost")]
+    PaymasterCostExceeded,
+    #[msg("Missing or invalid paymaster signature")]
+    InvalidPaymasterSignature,
+    #[msg("Paymaster validation is not valid at this time")]
+    PaymasterValidationExpired,
+    #[msg("Invalid lookup table")]
+    InvalidLookupTable,
+    #[msg("Wallet has no signature aggregator")]
+    NoAggregator,
+    #[msg("Operation requires co-signing")]
+    CoSigningRequired,
+    #[msg("Aggregator is not the wallet's aggregator")]
+    AggregatorMismatch,
+    #[msg("Calls cannot target this program outside guardian instructions")]
+    SelfCallNotAllowed,
+    #[msg("Only the wallet can sign for a call")]
+    UnauthorizedCallSigner,
+    #[msg("Sub-wallet calls can only transfer lamports")]
+    SubWalletProgramCall,
+    #[msg("Unfreeze delay has not elapsed")]
+    UnfreezeDelayNotElapsed,
+    #[msg("Wallet already uses the current layout")]
+    AlreadyMigrated,
+    #[msg("Signer is not the wallet owner")]
+    UnauthorizedOwner,
+}
+
+/// Shortest inactivity period an owner may configure (7 days)
+pub const MIN_INACTIVITY_PERIOD: i64 = 7 * 86400;
+
+/// Longest grace period an owner may configure for an inheritance claim (365 days)
+pub const MAX_GRACE_PERIOD: i64 = 365 * 86400;
+
+/// Longest delay an owner may put on key rotation (30 days)
+pub const MAX_OWNER_ROTATION_DELAY: i64 = 30 * 86400;
+
+/// Spending window slots available per wallet
+pub const MAX_SPENDING_WINDOWS: usize = 4;
+
+pub const SECONDS_PER_HOUR: i64 = 3600;
+pub const SECONDS_PER_DAY: i64 = 86400;
+pub const SECONDS_PER_WEEK: i64 = 7 * SECONDS_PER_DAY;
+/// Duration that selects calendar-month alignment (rolling windows treat it as 30 days)
+pub const SECONDS_PER_MONTH: i64 = 30 * SECONDS_PER_DAY;
+/// Longest rolling spending window (one year)
+pub const MAX_ROLLING_WINDOW: i64 = 365 * SECONDS_PER_DAY;
+
+/// Delay before a newly proposed emergency vault takes effect (14 days),
+/// long enough for guardians to cancel a change made with a stolen key
+pub const VAULT_CHANGE_DELAY: i64 = 14 * 24 * 60 * 60;
+
+/// Time a freeze holds before the owner may lift it (3 days)
+pub const UNFREEZE_DELAY: i64 = 3 * SECONDS_PER_DAY;
+
+/// Seed of the PDA a verifier-program guardian signs with, together with
+/// the protected wallet's address
+pub const GUARDIAN_VERIFIER_SEED: &[u8] = b"nexus_guardian";
+
+/// Native address lookup table program
+/// (`AddressLookupTab1e1111111111111111111111111`)
+pub const ADDRESS_LOOKUP_TABLE_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    2, 119, 166, 175, 151, 51, 155, 122,
+    200, 141, 24, 146, 201, 4, 70, 245,
+    0, 2, 48, 146, 102, 246, 46, 83,
+    193, 24, 36, 73, 130, 0, 0, 0,
+]);
+
+/// Longest revert reason carried by `UserOperationFailed`
+pub const MAX_REVERT_REASON_LEN: usize = 128;
+
+/// Price feed slots available per wallet
+pub const MAX_PRICE_FEEDS: usize = 4;
+
+/// Decimals of USD amounts in USD-denominated windows (micro-USD)
+pub const USD_DECIMALS: i32 = 6;
+pub const SOL_DECIMALS: u8 = 9;
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Longest window co-signers may be given to approve a queued operation (7 days)
+pub const MAX_CO_SIGN_WINDOW: i64 = 7 * 86400;
+
+/// Period over which lamports sent without co-signing add up towards the
+/// co-signing threshold
+pub const CO_SIGN_PERIOD: i64 = SECONDS_PER_DAY;
+
+// Helper Functions
+fn record_owner_activity(wallet: &mut Wallet, wallet_key: Pubkey) -> Result<()> {
+    wallet.last_activity = Clock::get()?.unix_timestamp;
+    
+    // Any owner activity cancels a pending inheritance claim
+    if wallet.inheritance.has_claim() {
+        wallet.inheritance.claim_started_at = 0;
+        
+        emit!(InheritanceClaimCancelled {
+            wallet: wallet_key,
+            owner: wallet.owner,
+        });
+    }
+    
+    Ok(())
+}
+
+fn validate_window_duration(duration: i64, mode: SpendingWindowMode) -> Result<()> {
+    let valid = match mode {
+        SpendingWindowMode::Rolling => (1..=MAX_ROLLING_WINDOW).contains(&duration),
+        SpendingWindowMode::CalendarAligned => matches!(
+            duration,
+            SECONDS_PER_HOUR | SECONDS_PER_DAY | SECONDS_PER_WEEK | SECONDS_PER_MONTH
+        ),
+    };
+    require!(valid, WalletError::InvalidSpendingWindow);
+    
+    Ok(())
+}
+
+/// Start of the calendar period (UTC) containing `time`
+fn calendar_period_start(time: i64, duration: i64) -> i64 {
+    match duration {
+        SECONDS_PER_MONTH => {
+            let (year, month, _) = civil_from_days(time.div_euclid(SECONDS_PER_DAY));
+            days_from_civil(year, month, 1) * SECONDS_PER_DAY
+        }
+        // 1970-01-05 was the first Monday after the epoch
+        SECONDS_PER_WEEK => time - (time - 4 * SECONDS_PER_DAY).rem_euclid(SECONDS_PER_WEEK),
+        _ => time - time.rem_euclid(duration),
+    }
+}
+
+/// End of the calendar period that starts at `start`
+fn calendar_period_end(start: i64, duration: i64) -> i64 {
+    match duration {
+        SECONDS_PER_MONTH => {
+            let (year, month, _) = civil_from_days(start.div_euclid(SECONDS_PER_DAY));
+            let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+            days_from_civil(year, month, 1) * SECONDS_PER_DAY
+        }
+        _ => start + duration,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian (year, month, day) for days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn decode_calls(call_data: &[u8]) -> Result<Vec<WalletCall>> {
+    if call_data.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    Vec::<WalletCall>::try_from_slice(call_data)
+        .map_err(|_| WalletError::InvalidCallData.into())
+}
+
+/// Total lamports an operation sends out of the wallet
+fn calls_value(calls: &[WalletCall]) -> Result<u64> {
+    calls.iter().try_fold(0u64, |total, call| {
+        total.checked_add(call.value).ok_or_else(|| WalletError::InvalidCallData.into())
+    })
+}
+
+fn find_call_account<'a, 'info>(
+    wallet: &'a AccountInfo<'info>,
+    remaining_accounts: &'a [AccountInfo<'info>],
+    key: &Pubkey,
+) -> Result<&'a AccountInfo<'info>> {
+    if wallet.key == key {
+        return Ok(wallet);
+    }
+    
+    remaining_accounts
+        .iter()
+        .find(|account| account.key == key)
+        .ok_or_else(|| WalletError::MissingCallAccount.into())
+}
+
+/// How a user operation's signature is verified
+enum SignatureCheck<'a, 'info> {
+    /// By an Ed25519 program instruction in the transaction, checked
+    /// through the instructions sysvar
+    Owner(&'a AccountInfo<'info>),
+    /// Already, by `aggregator` over `user_op_hash`, as the entry point
+    /// reports
+    Aggregated {
+        aggregator: Pubkey,
+        user_op_hash: [u8; 32],
+    },
+}
+
+/// Checks shared by executing and simulating a user operation; advances the
+/// nonce and records owner activity
+struct ValidatedOperation {
+    user_op_hash: [u8; 32],
+    signer: Pubkey,
+    calls: Vec<WalletCall>,
+    value: u64,
+    fee: u64,
+}
+
+fn validate_user_operation(
+    wallet: &mut Wallet,
+    wallet_key: Pubkey,
+    user_op: &UserOperation,
+    paymaster_data: Option<&PaymasterData>,
+    signature_check: SignatureCheck,
+) -> Result<ValidatedOperation> {
+    // Verify wallet is not frozen
+    require!(!wallet.is_frozen(), WalletError::WalletFrozen);
+    
+    // Verify nonce
+    require!(user_op.nonce == wallet.nonce, WalletError::InvalidNonce);
+    
+    // Increment nonce
+    wallet.nonce += 1;
+    
+    // Gas the wallet pays itself counts towards its spending windows
+    let fee = if paymaster_data.is_none() { user_op.max_fee_per_gas } else { 0 };
+    
+    // Validate user operation signature
+    let user_op_hash = user_op.canonical_hash();
+    let signer = match signature_check {
+        SignatureCheck::Owner(instructions_sysvar) => {
+            require!(
+                verify_signature(instructions_sysvar, &user_op_hash, &user_op.signature, &wallet.owner)?,
+                WalletError::InvalidSignature
+            );
+            wallet.owner
+        }
+        SignatureCheck::Aggregated { aggregator, user_op_hash: verified_hash } => {
+            // The aggregator verified the signature over this exact hash,
+            // which binds the sender
+            require!(wallet.has_aggregator(), WalletError::NoAggregator);
+            require_keys_eq!(aggregator, wallet.aggregator, WalletError::AggregatorMismatch);
+            require!(verified_hash == user_op_hash, WalletError::InvalidSignature);
+            require!(user_op.sender == wallet_key, WalletError::InvalidSignature);
+            wallet.aggregator
+        }
+    };
+    
+    record_owner_activity(wallet, wallet_key)?;
+    
+    let calls = decode_calls(&user_op.call_data)?;
+    let value = calls_value(&calls)?;
+    
+    Ok(ValidatedOperation {
+        user_op_hash,
+        signer,
+        calls,
+        value,
+        fee,
+    })
+}
+
+/// Lamport and token balance of one account, for simulation reports
+struct AccountBalance {
+    key: Pubkey,
+    lamports: u64,
+    token: Option<(Pubkey, u64)>,
+}
+
+impl AccountBalance {
+    fn delta_from(&self, before: &AccountBalance) -> Option<BalanceDelta> {
+        let lamports = self.lamports as i128 - before.lamports as i128;
+        let token = match (self.token, before.token) {
+            (Some((mint, after)), Some((_, before))) if after != before => {
+                Some((mint, after as i128 - before as i128))
+            }
+            _ => None,
+        };
+        
+        (lamports != 0 || token.is_some()).then_some(BalanceDelta {
+            account: self.key,
+            lamports,
+            token,
+        })
+    }
+}
+
+/// Balances of the wallet followed by each distinct passed account
+fn account_balances<'info>(
+    wallet: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+) -> Vec<AccountBalance> {
+    let mut balances: Vec<AccountBalance> = Vec::with_capacity(accounts.len() + 1);
+    for account in std::iter::once(wallet).chain(accounts) {
+        if balances.iter().any(|b| b.key == *account.key) {
+            continue;
+        }
+        balances.push(AccountBalance {
+            key: *account.key,
+            lamports: account.lamports(),
+            token: read_token_account(account).map(|(mint, _, amount)| (mint, amount)),
+        });
+    }
+    balances
+}
+
+/// Read (mint, owner, amount) from an SPL token account
+fn read_token_account(account: &AccountInfo) -> Option<(Pubkey, Pubkey, u64)> {
+    if account.owner != &anchor_spl::token::ID {
+        return None;
+    }
+    
+    let data = account.try_borrow_data().ok()?;
+    if data.len() != TokenAccount::LEN {
+        return None;
+    }
+    
+    let mint = Pubkey::try_from(&data[0..32]).ok()?;
+    let owner = Pubkey::try_from(&data[32..64]).ok()?;
+    let amount = u64::from_le_bytes(data[64..72].try_into().ok()?);
+    Some((mint, owner, amount))
+}
+
+/// Balances of the token accounts in `accounts` that belong to `wallet`
+fn wallet_token_balances(wallet: &Pubkey, accounts: &[AccountInfo]) -> Vec<(usize, Pubkey, u64)> {
+    accounts
+        .iter()
+        .enumerate()
+        .filter_map(|(index, account)| match read_token_account(account) {
+            Some((mint, owner, amount)) if owner == *wallet => Some((index, mint, amount)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Add `amount` of `mint` to a per-mint list of outflows
+fn add_token_outflow(outflows: &mut Vec<(Pubkey, u64)>, mint: Pubkey, amount: u64) {
+    match outflows.iter_mut().find(|(m, _)| *m == mint) {
+        Some((_, total)) => *total = total.saturating_add(amount),
+        None => outflows.push((mint, amount)),
+    }
+}
+
+/// Calls made and assets moved by `execute_calls`
+struct ExecutionOutcome {
+    call_results: Vec<CallResult>,
+    /// Amount of each mint that left the wallet's token accounts
+    token_outflows: Vec<(Pubkey, u64)>,
+}
+
+/// An error raised by the wallet itself while running calls, with the
+/// index of the call being run (`None` for checks after the last call)
+struct ExecutionFailure {
+    call: Option<u8>,
+    error: Error,
+}
+
+/// Run the calls of an operation with the wallet PDA as signer, measuring
+/// the wallet's token accounts passed in `remaining_accounts`. The wallet's
+/// account data must not be borrowed while this runs.
+fn execute_calls<'info>(
+    wallet: &AccountInfo<'info>,
+    calls: &[WalletCall],
+    remaining_accounts: &[AccountInfo<'info>],
+    signer_seeds: &[&[&[u8]]],
+) -> std::result::Result<ExecutionOutcome, ExecutionFailure> {
+    let balances_before = wallet_token_balances(wallet.key, remaining_accounts);
+    let mut call_results = Vec::with_capacity(calls.len());
+    
+    for (index, call) in calls.iter().enumerate() {
+        let result = execute_call(wallet, call, remaining_accounts, signer_seeds)
+            .map_err(|error| ExecutionFailure { call: Some(index as u8), error })?;
+        call_results.push(result);
+    }
+    
+    let failure = |error: Error| ExecutionFailure { call: None, error };
+    let rent_exempt = Rent::get().map_err(|e| failure(e.into()))?
+        .minimum_balance(wallet.data_len());
+    if wallet.lamports() < rent_exempt {
+        return Err(failure(WalletError::InsufficientFunds.into()));
+    }
+    
+    let mut token_outflows = Vec::new();
+    for (index, mint, before) in balances_before {
+        let after = read_token_account(&remaining_accounts[index])
+            .map_or(0, |(_, _, amount)| amount);
+        if after < before {
+            add_token_outflow(&mut token_outflows, mint, before - after);
+        }
+    }
+    
+    Ok(ExecutionOutcome {
+        call_results,
+        token_outflows,
+    })
+}
+
+/// Run a single call: send its value, then invoke `target` if it has data
+fn execute_call<'info>(
+    wallet: &AccountInfo<'info>,
+    call: &WalletCall,
+    remaining_accounts: &[AccountInfo<'info>],
+    signer_seeds: &[&[&[u8]]],
+) -> Result<CallResult> {
+    let balances_before = wallet_token_balances(wallet.key, remaining_accounts);
+    
+    if call.value > 0 {
+        let recipient = find_call_account(wallet, remaining_accounts, &call.target)?;
+        let remaining = wallet.lamports()
+            .checked_sub(call.value)
+            .ok_or(WalletError::InsufficientFunds)?;
+        
+        **wallet.try_borrow_mut_lamports()? = remaining;
+        **recipient.try_borrow_mut_lamports()? += call.value;
+    }
+    
+    let mut return_data = Vec::new();
+    let mut compute_units = 0;
+    if !call.data.is_empty() {
+        // The wallet may only call itself to act as another wallet's
+        // guardian, and only its own signature is passed on: anything else
+        // would let a call reuse a signer of the instruction running it
+        require!(
+            call.target != crate::ID || is_guardian_instruction(&call.data),
+            WalletError::SelfCallNotAllowed
+        );
+        require!(
+            call.accounts.iter().all(|account| !account.is_signer || account.pubkey == *wallet.key),
+            WalletError::UnauthorizedCallSigner
+        );
+        
+        let program = find_call_account(wallet, remaining_accounts, &call.target)?;
+        let mut account_infos = Vec::with_capacity(call.accounts.len() + 1);
+        let mut account_metas = Vec::with_capacity(call.accounts.len());
+        
+        for account in &call.accounts {
+            account_infos.push(find_call_account(wallet, remaining_accounts, &account.pubkey)?.clone());
+            account_metas.push(AccountMeta {
+                pubkey: account.pubkey,
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            });
+        }
+        account_infos.push(program.clone());
+        
+        let start = remaining_compute_units();
+        invoke_signed(
+            &Instruction {
+                program_id: call.target,
+                accounts: account_metas,
+                data: call.data.clone(),
+            },
+            &account_infos,
+            signer_seeds,
+        )?;
+        compute_units = start.saturating_sub(remaining_compute_units());
+        
+        return_data = get_return_data()
+            .filter(|(program_id, _)| *program_id == call.target)
+            .map(|(_, data)| data)
+            .unwrap_or_default();
+    }
+    
+    // Differences of u64 amounts, and their sums, fit an i128
+    let mut token_deltas: Vec<(Pubkey, i128)> = Vec::new();
+    for (index, mint, before) in balances_before {
+        let after = read_token_account(&remaining_accounts[index])
+            .map_or(0, |(_, _, amount)| amount);
+        let delta = after as i128 - before as i128;
+        if delta == 0 {
+            continue;
+        }
+        match token_deltas.iter_mut().find(|(m, _)| *m == mint) {
+            Some((_, total)) => *total += delta,
+            None => token_deltas.push((mint, delta)),
+        }
+    }
+    
+    Ok(CallResult {
+        target: call.target,
+        value: call.value,
+        token_deltas,
+        compute_units,
+        return_data,
+    })
+}
+
+/// Whether `data` invokes one of this program's instructions that a
+/// wallet guardian signs by CPI
+fn is_guardian_instruction(data: &[u8]) -> bool {
+    use anchor_lang::Discriminator;
+    
+    let guardian_instructions = [
+        instruction::InitiateRecovery::DISCRIMINATOR,
+        instruction::ApproveRecovery::DISCRIMINATOR,
+        instruction::FreezeWallet::DISCRIMINATOR,
+        instruction::CancelVaultChange::DISCRIMINATOR,
+        instruction::ApproveVaultSweep::DISCRIMINATOR,
+        instruction::CancelOwnerRotation::DISCRIMINATOR,
+        instruction::ApprovePendingOperation::DISCRIMINATOR,
+        instruction::CancelPendingOperation::DISCRIMINATOR,
+    ];
+    data.get(..8)
+        .is_some_and(|discriminator| guardian_instructions.iter().any(|d| d[..] == *discriminator))
+}
+
+/// Execute a validated user operation's calls, charge its spending windows
+/// and emit the execution events. `wallet` must not be borrowed.
+/// `unsigned_value` is the lamport value of an operation that was not
+/// co-signed, which together with its token outflows counts towards the
+/// co-signing threshold.
+#[allow(clippy::too_many_arguments)]
+fn run_user_operation<'info>(
+    wallet: &AccountLoader<'info, Wallet>,
+    remaining_accounts: &[AccountInfo<'info>],
+    user_op_hash: &[u8; 32],
+    nonce: u64,
+    calls: &[WalletCall],
+    lamports: u64,
+    unsigned_value: Option<u64>,
+    current_time: i64,
+) -> Result<()> {
+    let wallet_key = wallet.key();
+    let (initial_owner, recovery_hash, bump) = {
+        let wallet = wallet.load()?;
+        (wallet.initial_owner, wallet.recovery_hash, wallet.bump)
+    };
+    
+    let seeds = &[
+        b"wallet".as_ref(),
+        initial_owner.as_ref(),
+        recovery_hash.as_ref(),
+        &[bump],
+    ];
+    
+    let report_failure = |failure: ExecutionFailure| {
+        emit!(UserOperationFailed {
+            wallet: wallet_key,
+            user_op_hash: *user_op_hash,
+            nonce,
+            failed_call: failure.call,
+            reason: revert_reason(&failure.error),
+        });
+        failure.error
+    };
+    
+    let outcome = execute_calls(&wallet.to_account_info(), calls, remaining_accounts, &[seeds])
+        .map_err(report_failure)?;
+    
+    // Limits are charged after execution so that token transfers made by
+    // the calls are measured rather than declared; exceeding one reverts
+    // the whole operation
+    let spend = SpendAmounts {
+        lamports,
+        tokens: outcome.token_outflows,
+    };
+    let mut wallet_state = wallet.load_mut()?;
+    wallet_state
+        .consume_spending(&spend, remaining_accounts, current_time)
+        .and_then(|()| match unsigned_value {
+            Some(value) => wallet_state.record_unsigned_spend(
+                value,
+                &spend.tokens,
+                remaining_accounts,
+                current_time,
+            ),
+            None => Ok(()),
+        })
+        .map_err(|error| report_failure(ExecutionFailure { call: None, error }))?;
+    drop(wallet_state);
+    
+    for (index, result) in outcome.call_results.iter().enumerate() {
+        emit!(UserOperationCallExecuted {
+            wallet: wallet_key,
+            user_op_hash: *user_op_hash,
+            index: index as u8,
+            target: result.target,
+            lamports: result.value,
+            token_deltas: result.token_deltas.clone(),
+            compute_units: result.compute_units,
+        });
+    }
+    
+    emit!(UserOperationExecuted {
+        wallet: wallet_key,
+        user_op_hash: *user_op_hash,
+        nonce,
+        calls: outcome.call_results.len() as u8,
+        lamports_spent: lamports,
+        token_outflows: spend.tokens,
+    });
+    
+    Ok(())
+}
+
+/// Human-readable message of `error`, truncated to `MAX_REVERT_REASON_LEN`
+fn revert_reason(error: &Error) -> String {
+    let mut reason = match error {
+        Error::AnchorError(error) => error.error_msg.clone(),
+        Error::ProgramError(error) => error.program_error.to_string(),
+    };
+    
+    if reason.len() > MAX_REVERT_REASON_LEN {
+        let mut end = MAX_REVERT_REASON_LEN;
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        reason.truncate(end);
+    }
+    reason
+}
+
+/// Move lamports between two accounts owned by this program, keeping the
+/// source rent-exempt
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let rent_exempt = Rent::get()?.minimum_balance(from.data_len());
+    let remaining = from.lamports()
+        .checked_sub(amount)
+        .filter(|remaining| *remaining >= rent_exempt)
+        .ok_or(WalletError::InsufficientFunds)?;
+    
+    **from.try_borrow_mut_lamports()? = remaining;
+    **to.try_borrow_mut_lamports()? += amount;
+    
+    Ok(())
+}
+
+/// Operation queued by `fund_sub_wallet` when funding needs co-signers:
+/// an unsigned transfer of `amount` lamports from `wallet` to `sub_wallet`
+/// under `nonce`. Pass it to `execute_pending_operation` once co-signed,
+/// with `sub_wallet` in remaining accounts.
+pub fn sub_wallet_funding_operation(
+    wallet: Pubkey,
+    sub_wallet: Pubkey,
+    amount: u64,
+    nonce: u64,
+) -> Result<UserOperation> {
+    let calls = vec![WalletCall {
+        target: sub_wallet,
+        accounts: Vec::new(),
+        data: Vec::new(),
+        value: amount,
+    }];
+    
+    Ok(UserOperation {
+        sender: wallet,
+        nonce,
+        init_code: Vec::new(),
+        call_data: calls.try_to_vec()?,
+        call_gas_limit: 0,
+        verification_gas_limit: 0,
+        pre_verification_gas: 0,
+        max_fee_per_gas: 0,
+        max_priority_fee_per_gas: 0,
+        paymaster_and_data: Vec::new(),
+        signature: [0; 64],
+    })
+}
+
+/// Message a paymaster's owner signs to sponsor an operation for `wallet`,
+/// covering up to `max_cost` lamports of fees
+pub fn paymaster_authorization_message(
+    user_op_hash: &[u8; 32],
+    wallet: &Pubkey,
+    paymaster: &Pubkey,
+    max_cost: u64,
+) -> [u8; 32] {
+    anchor_lang::solana_program::hash::hashv(&[
+        b"nexus_paymaster_authorization",
+        user_op_hash,
+        wallet.as_ref(),
+        paymaster.as_ref(),
+        &max_cost.to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+/// Check the paymaster's signature over the operation and have the
+/// paymaster program validate it, returning the context for `post_op`
+fn validate_paymaster(
+    accounts: &ExecuteUserOperation,
+    user_op_hash: &[u8; 32],
+    paymaster_data: &PaymasterData,
+) -> Result<Vec<u8>> {
+    let paymaster = accounts.paymaster.as_ref()
+        .ok_or(WalletError::MissingPaymasterAccounts)?;
+    let paymaster_data_account = accounts.paymaster_data_account.as_ref()
+        .ok_or(WalletError::MissingPaymasterAccounts)?;
+    let paymaster_program = accounts.paymaster_program.as_ref()
+        .ok_or(WalletError::MissingPaymasterAccounts)?;
+    
+    require!(paymaster.key() == paymaster_data.paymaster, WalletError::InvalidPaymaster);
+    
+    let message = paymaster_authorization_message(
+        user_op_hash,
+        &accounts.wallet.key(),
+        &paymaster.key(),
+        paymaster_data.max_cost,
+    );
+    verify_ed25519_instruction(
+        &accounts.instructions_sysvar,
+        &paymaster.owner,
+        &message,
+        &paymaster_data.signature,
+    )?;
+    
+    let validation = nexus_paymaster::cpi::validate_paymaster_user_op(
+        CpiContext::new(
+            paymaster_program.to_account_info(),
+            nexus_paymaster::cpi::accounts::ValidatePaymasterUserOp {
+                paymaster: paymaster.to_account_info(),
+                user_account: accounts.wallet.to_account_info(),
+                paymaster_data: paymaster_data_account.to_account_info(),
+            },
+        ),
+        *user_op_hash,
+        paymaster_data.max_cost,
+    )?
+    .get();
+    
+    let now = Clock::get()?.unix_timestamp.max(0) as u64;
+    require!(
+        validation.valid_after <= now && now <= validation.valid_until,
+        WalletError::PaymasterValidationExpired
+    );
+    
+    Ok(validation.context)
+}
+
+/// Report `cost` lamports of fees to a validated paymaster through
+/// `post_op`, which only updates its totals: no lamports move
+fn record_paymaster_cost(accounts: &ExecuteUserOperation, context: Vec<u8>, cost: u64) -> Result<()> {
+    let paymaster = accounts.paymaster.as_ref()
+        .ok_or(WalletError::MissingPaymasterAccounts)?;
+    let paymaster_program = accounts.paymaster_program.as_ref()
+        .ok_or(WalletError::MissingPaymasterAccounts)?;
+    
+    nexus_paymaster::cpi::post_op(
+        CpiContext::new(
+            paymaster_program.to_account_info(),
+            nexus_paymaster::cpi::accounts::PostOp {
+                paymaster: paymaster.to_account_info(),
+            },
+        ),
+        PostOpMode::OpSucceeded,
+        context,
+        cost,
+    )
+}
+
+
+/// Require an Ed25519 program instruction in this transaction that verified
+/// `signature` by `signer` over `message`. The precompile has already
+/// checked the signature itself; this confirms it covered our values.
+fn verify_ed25519_instruction(
+    instructions_sysvar: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Result<()> {
+    require!(
+        verify_signature(instructions_sysvar, message, signature, signer)?,
+        WalletError::InvalidPaymasterSignature
+    );
+    Ok(())
+}
+
+/// Whether Ed25519 program instruction data holds a single signature whose
+/// key, signature and message are inline and equal to the expected ones
+fn ed25519_instruction_matches(
+    data: &[u8],
+    signer: &Pubkey,
+    message: &[u8],
+    signature: &[u8; 64],
+) -> bool {
+    // [count: u8, padding: u8] followed by one 14-byte offsets record
+    const HEADER_LEN: usize = 2 + 14;
+    const THIS_INSTRUCTION: u16 = u16::MAX;
+    
+    if data.len() < HEADER_LEN || data[0] != 1 {
+        return false;
+    }
+    
+    let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let signature_offset = read_u16(2) as usize;
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    
+    if [read_u16(4), read_u16(8), read_u16(14)].iter().any(|i| *i != THIS_INSTRUCTION) {
+        return false;
+    }
+    
+    let slice = |offset: usize, len: usize| data.get(offset..offset.checked_add(len)?);
+    slice(signature_offset, 64) == Some(signature.as_ref())
+        && slice(public_key_offset, 32) == Some(signer.as_ref())
+        && message_size == message.len()
+        && slice(message_offset, message_size) == Some(message)
+}
+
+/// Address (and bump) of the lookup table `authority` creates at `recent_slot`
+pub fn lookup_table_address(authority: &Pubkey, recent_slot: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[authority.as_ref(), &recent_slot.to_le_bytes()],
+        &ADDRESS_LOOKUP_TABLE_PROGRAM_ID,
+    )
+}
+
+/// Instructions of the address lookup table program used by the wallet
+enum LookupTableInstruction {
+    Create { recent_slot: u64, bump_seed: u8 },
+    Extend(Vec<Pubkey>),
+    Deactivate,
+    Close,
+}
+
+/// Encode an instruction the way the lookup table program expects
+/// (bincode: u32 variant index, u64 vector lengths)
+fn lookup_table_instruction_data(instruction: LookupTableInstruction) -> Vec<u8> {
+    let mut data = Vec::new();
+    match instruction {
+        LookupTableInstruction::Create { recent_slot, bump_seed } => {
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&recent_slot.to_le_bytes());
+            data.push(bump_seed);
+        }
+        LookupTableInstruction::Extend(addresses) => {
+            data.extend_from_slice(&2u32.to_le_bytes());
+            data.extend_from_slice(&(addresses.len() as u64).to_le_bytes());
+            for address in addresses {
+                data.extend_from_slice(address.as_ref());
+            }
+        }
+        LookupTableInstruction::Deactivate => data.extend_from_slice(&3u32.to_le_bytes()),
+        LookupTableInstruction::Close => data.extend_from_slice(&4u32.to_le_bytes()),
+    }
+    data
+}
+
+/// Create the `PendingOperation` PDA for an operation that needs co-signers
+fn queue_pending_operation<'info>(
+    pending_operation: Option<&UncheckedAccount<'info>>,
+    payer: Option<&Signer<'info>>,
+    system_program: Option<&Program<'info, System>>,
+    mut pending: PendingOperation,
+) -> Result<()> {
+    let pending_info = pending_operation.ok_or(WalletError::MissingPendingOperation)?;
+    let payer = payer.ok_or(WalletError::MissingPendingOperation)?;
+    let system_program = system_program.ok_or(WalletError::MissingPendingOperation)?;
+    
+    let nonce_bytes = pending.nonce.to_le_bytes();
+    let (expected_key, bump) = Pubkey::find_program_address(
+        &[b"pending_op", pending.wallet.as_ref(), &nonce_bytes],
+        &crate::ID,
+    );
+    require!(pending_info.key() == expected_key, WalletError::MissingPendingOperation);
+    
+    let space = 8 + PendingOperation::INIT_SPACE;
+    create_account(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            CreateAccount {
+                from: payer.to_account_info(),
+                to: pending_info.to_account_info(),
+            },
+            &[&[b"pending_op", pending.wallet.as_ref(), &nonce_bytes, &[bump]]],
+        ),
+        Rent::get()?.minimum_balance(space),
+        space as u64,
+        &crate::ID,
+    )?;
+    
+    pending.rent_payer = payer.key();
+    pending.try_serialize(&mut &mut pending_info.try_borrow_mut_data()?[..])?;
+    
+    Ok(())
+}
+
+fn sweep_token_accounts<'info>(
+    wallet: &AccountInfo<'info>,
+    token_accounts: &[AccountInfo<'info>],
+    token_program: &AccountInfo<'info>,
+    destination_owner: Option<&Pubkey>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let pairs = token_accounts.chunks_exact(2);
+    require!(pairs.remainder().is_empty(), WalletError::InvalidTokenAccount);
+    
+    for pair in pairs {
+        let source = Account::<TokenAccount>::try_from(&pair[0])?;
+        require!(source.owner == wallet.key(), WalletError::InvalidTokenAccount);
+        
+        if let Some(owner) = destination_owner {
+            let destination = Account::<TokenAccount>::try_from(&pair[1])?;
+            require!(destination.owner == *owner, WalletError::InvalidTokenAccount);
+        }
+        
+        if source.amount == 0 {
+            continue;
+        }
+        
+        transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                Transfer {
+                    from: pair[0].clone(),
+                    to: pair[1].clone(),
+                    authority: wallet.clone(),
+                },
+                signer_seeds,
+            ),
+            source.amount,
+        )?;
+    }
+    
+    Ok(())
+}
+/// Whether an Ed25519 program instruction in this transaction verified
+/// `signature` by `expected_pubkey` over `message`. The precompile fails
+/// the transaction on a bad signature, so a matching instruction proves it.
+fn verify_signature(
+    instructions_sysvar: &AccountInfo,
+    message: &[u8],
+    signature: &[u8; 64],
+    expected_pubkey: &Pubkey,
+) -> Result<bool> {
+    require_keys_eq!(
+        *instructions_sysvar.key,
+        sysvar_instructions::ID,
+        WalletError::InvalidSignature
+    );
+    
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        if instruction.program_id == ed25519_program::ID
+            && ed25519_instruction_matches(&instruction.data, expected_pubkey, message, signature)
+        {
+            return Ok(true);
+        }
+        index += 1;
+    }
+    
+    Ok(false)
+}
+
+// Cross-chain compatibility helpers
+pub fn derive_unified_wallet_address(
+    owner: &Pubkey,
+    recovery_hash: &[u8; 32],
+) -> Result<(Pubkey, u8)> {
+    Ok(Pubkey::find_program_address(
+        &[b"wallet", owner.as_ref(), recovery_hash],
+        &ID,
+    ))
+}
+
+pub fn validate_cross_chain_operation(
+    _accounts: &[AccountInfo],
+    _user_op: &UserOperation,
+    _wallet_key: Pubkey,
+    _bump: &u8,
+) -> Result<()> {
+    // Cross-chain operation validation logic
+    // This would integrate with the bridge program for cross-chain operations
+    Ok(())
+} 
+
+impl Wallet {
+    /// Guardians currently occupying slots
+    pub fn guardian_keys(&self) -> &[Pubkey] {
+        &self.guardians[..self.guardian_count as usize]
+    }
+    
+    pub fn guardian_index(&self, guardian: &Pubkey) -> Option<usize> {
+        self.guardian_keys().iter().position(|g| g == guardian)
+    }
+    
+    pub fn guardian_kind(&self, index: usize) -> GuardianKind {
+        match self.guardian_kinds[index] {
+            1 => GuardianKind::Wallet,
+            2 => GuardianKind::Verifier,
+            _ => GuardianKind::Key,
+        }
+    }
+    
+    /// Slot of the guardian that `signer` acts for, whatever its kind
+    pub fn guardian_signer_index(&self, signer: &AccountInfo) -> Option<usize> {
+        if !signer.is_signer {
+            return None;
+        }
+        
+        self.guardian_keys().iter().enumerate().position(|(index, guardian)| {
+            match self.guardian_kind(index) {
+                GuardianKind::Key => signer.key == guardian,
+                GuardianKind::Wallet => signer.key == guardian && signer.owner == &crate::ID,
+                GuardianKind::Verifier => *signer.key == self.guardian_attestors[index],
+            }
+        })
+    }
+    
+    /// Change the owner rotation delay, returning when the change applies.
+    /// Increases are immediate; decreases wait out the current delay.
+    pub fn schedule_rotation_delay(&mut self, delay: i64, current_time: i64) -> i64 {
+        if delay >= self.owner_rotation_delay {
+            self.owner_rotation_delay = delay;
+            self.pending_rotation_delay = 0;
+            self.rotation_delay_change_at = 0;
+            return current_time;
+        }
+        
+        let effective_at = current_time + self.owner_rotation_delay;
+        self.pending_rotation_delay = delay;
+        self.rotation_delay_change_at = effective_at;
+        effective_at
+    }
+    
+    /// Apply a scheduled rotation delay decrease once its time has come
+    pub fn settle_rotation_delay(&mut self, current_time: i64) {
+        if self.rotation_delay_change_at != 0 && current_time >= self.rotation_delay_change_at {
+            self.owner_rotation_delay = self.pending_rotation_delay;
+            self.pending_rotation_delay = 0;
+            self.rotation_delay_change_at = 0;
+        }
+    }
+    
+    /// Remove the guardian at `index` by moving the last guardian into its
+    /// slot, carrying that guardian's approval bits along with it.
+    pub fn remove_guardian_at(&mut self, index: usize) {
+        let last = self.guardian_count as usize - 1;
+        
+        for approvals in [
+            &mut self.pending_recovery.guardian_approvals,
+            &mut self.vault_sweep_approvals,
+        ] {
+            *approvals &= !(1u32 << index);
+            if index != last {
+                if *approvals & (1u32 << last) != 0 {
+                    *approvals |= 1u32 << index;
+                }
+                *approvals &= !(1u32 << last);
+            }
+        }
+        
+        if index != last {
+            self.guardians[index] = self.guardians[last];
+            self.guardian_kinds[index] = self.guardian_kinds[last];
+            self.guardian_attestors[index] = self.guardian_attestors[last];
+        }
+        
+        self.guardians[last] = Pubkey::default();
+        self.guardian_kinds[last] = GuardianKind::Key as u8;
+        self.guardian_attestors[last] = Pubkey::default();
+        self.guardian_count -= 1;
+    }
+    
+    /// Majority of the current guardian set
+    pub fn required_approvals(&self) -> u32 {
+        (self.guardian_count as u32 / 2) + 1
+    }
+    
+    pub fn has_aggregator(&self) -> bool {
+        self.aggregator != Pubkey::default()
+    }
+    
+    pub fn is_frozen(&self) -> bool {
+        self.is_frozen != 0
+    }
+    
+    /// Fill a zeroed wallet from its `LegacyWallet` state. The daily limit
+    /// becomes a rolling daily window, and a frozen wallet starts its
+    /// `UNFREEZE_DELAY` at the migration.
+    fn migrate_from(&mut self, legacy: &LegacyWallet, initial_owner: Pubkey, bump: u8, now: i64) {
+        self.owner = legacy.owner;
+        self.initial_owner = initial_owner;
+        self.recovery_hash = legacy.recovery_hash;
+        self.nonce = legacy.nonce;
+        self.initialized = legacy.initialized as u8;
+        self.bump = bump;
+        self.last_activity = now;
+        
+        let guardians = legacy.guardians.iter().take(MAX_GUARDIANS);
+        for (slot, guardian) in self.guardians.iter_mut().zip(guardians) {
+            *slot = *guardian;
+        }
+        self.guardian_count = legacy.guardians.len().min(MAX_GUARDIANS) as u8;
+        
+        if let Some(recovery) = &legacy.pending_recovery {
+            self.pending_recovery = RecoveryRequest {
+                new_owner: recovery.new_owner,
+                initiated_at: recovery.initiated_at,
+                guardian_approvals: recovery.guardian_approvals
+                    .iter()
+                    .filter_map(|approver| self.guardian_index(approver))
+                    .fold(0, |bitmap, index| bitmap | 1 << index),
+                is_active: 1,
+                _padding: [0; 3],
+            };
+        }
+        
+        let mut window = SpendingWindow::new(
+            legacy.daily_limit,
+            SECONDS_PER_DAY,
+            SpendingWindowMode::Rolling,
+            legacy.last_reset,
+        );
+        window.spent = legacy.daily_spent;
+        self.spending_windows[0] = window;
+        
+        if legacy.is_frozen {
+            self.is_frozen = 1;
+            self.frozen_at = now;
+        }
+    }
+    
+    /// Freeze the wallet at `now`, which starts the `UNFREEZE_DELAY`
+    fn freeze(&mut self, now: i64) -> Result<()> {
+        require!(!self.is_frozen(), WalletError::WalletFrozen);
+        self.is_frozen = 1;
+        self.frozen_at = now;
+        Ok(())
+    }
+    
+    /// Earliest time the owner can unfreeze the wallet
+    fn unfreeze_time(&self) -> i64 {
+        self.frozen_at.saturating_add(UNFREEZE_DELAY)
+    }
+    
+    pub fn has_pending_vault(&self) -> bool {
+        self.pending_vault != Pubkey::default()
+    }
+    
+    /// Charge `spend` against every active spending window, failing if any
+    /// of them would be exceeded. Price feed accounts for USD windows are
+    /// looked up in `price_accounts`.
+    pub fn consume_spending(
+        &mut self,
+        spend: &SpendAmounts,
+        price_accounts: &[AccountInfo],
+        current_time: i64,
+    ) -> Result<()> {
+        let needs_usd = self.spending_windows
+            .iter()
+            .any(|w| w.is_active() && w.denomination() == SpendingDenomination::Usd);
+        let usd_amount = if needs_usd {
+            self.usd_value(spend, price_accounts, current_time)?
+        } else {
+            0
+        };
+        
+        for window in self.spending_windows.iter_mut().filter(|w| w.is_active()) {
+            let amount = match window.denomination() {
+                SpendingDenomination::Lamports => spend.lamports,
+                SpendingDenomination::Usd => usd_amount,
+            };
+            
+            window.advance(current_time);
+            
+            let used = window.used(current_time)
+                .checked_add(amount)
+                .ok_or(WalletError::DailyLimitExceeded)?;
+            require!(used <= window.limit, WalletError::DailyLimitExceeded);
+            
+            window.spent += amount;
+        }
+        
+        Ok(())
+    }
+    
+    /// Micro-USD value of `spend`, using the configured feed for each asset
+    pub fn usd_value(
+        &self,
+        spend: &SpendAmounts,
+        price_accounts: &[AccountInfo],
+        current_time: i64,
+    ) -> Result<u64> {
+        let assets = std::iter::once((Pubkey::default(), spend.lamports))
+            .chain(spend.tokens.iter().copied())
+            .filter(|(_, amount)| *amount > 0);
+        
+        let mut total = 0u64;
+        for (mint, amount) in assets {
+            let value = self.asset_usd_value(mint, amount, price_accounts, current_time)?;
+            total = total.checked_add(value).ok_or(WalletError::DailyLimitExceeded)?;
+        }
+        
+        Ok(total)
+    }
+    
+    /// Take `amount` from `allowance` and charge it to the spending windows
+    pub fn spend_allowance(
+        &mut self,
+        allowance: &mut Allowance,
+        amount: u64,
+        price_accounts: &[AccountInfo],
+        current_time: i64,
+    ) -> Result<()> {
+        require!(!self.is_frozen(), WalletError::WalletFrozen);
+        
+        if let Some(expiry) = allowance.expires_at {
+            require!(current_time < expiry, WalletError::AllowanceExpired);
+        }
+        
+        require!(amount <= allowance.amount, WalletError::AllowanceExceeded);
+        
+        self.consume_spending(
+            &SpendAmounts { lamports: 0, tokens: vec![(allowance.mint, amount)] },
+            price_accounts,
+            current_time,
+        )?;
+        allowance.amount -= amount;
+        
+        Ok(())
+    }
+    
+    /// Distinct guardians and second factor among the signers of `accounts`
+    pub fn co_signature_count(&self, accounts: &[AccountInfo]) -> u32 {
+        let mut guardian_approvals = 0u32;
+        let mut second_factor_approved = false;
+        
+        for account in accounts.iter().filter(|a| a.is_signer) {
+            if self.second_factor != Pubkey::default() && account.key() == self.second_factor {
+                second_factor_approved = true;
+            } else if let Some(index) = self.guardian_signer_index(account) {
+                guardian_approvals |= 1 << index;
+            }
+        }
+        
+        guardian_approvals.count_ones() + second_factor_approved as u32
+    }
+    
+    /// Whether a feed for `mint` is configured and passed in `price_accounts`
+    fn has_price_feed(&self, mint: &Pubkey, price_accounts: &[AccountInfo]) -> bool {
+        self.price_feeds
+            .iter()
+            .find(|f| f.is_active() && f.mint == *mint)
+            .is_some_and(|config| price_accounts.iter().any(|a| a.key() == config.feed))
+    }
+    
+    /// Micro-USD value of `amount` of `mint` (the default key for SOL)
+    fn asset_usd_value(
+        &self,
+        mint: Pubkey,
+        amount: u64,
+        price_accounts: &[AccountInfo],
+        current_time: i64,
+    ) -> Result<u64> {
+        let config = self.price_feeds
+            .iter()
+            .find(|f| f.is_active() && f.mint == mint)
+            .ok_or(WalletError::MissingPriceFeed)?;
+        let account = price_accounts
+            .iter()
+            .find(|a| a.key() == config.feed)
+            .ok_or(WalletError::MissingPriceFeed)?;
+        
+        let price = PriceFeedData::load(account)?;
+        config.usd_value(&price, amount, current_time)
+    }
+    
+    /// Whether an operation making `calls`, which send `value` lamports,
+    /// must be queued for co-signers before it runs. While co-signing is
+    /// enabled that is any operation whose value, added to what was sent
+    /// without co-signing in the current `CO_SIGN_PERIOD`, exceeds the
+    /// threshold, and with `co_sign_program_calls` any operation calling a
+    /// program other than for guardian duties.
+    ///
+    /// The tokens a program call moves are only known once it has run, so
+    /// other program calls are valued afterwards by `record_unsigned_spend`.
+    pub fn requires_co_signing(&self, calls: &[WalletCall], value: u64, current_time: i64) -> bool {
+        if self.co_sign_threshold == 0 {
+            return false;
+        }
+        
+        let calls_program = self.co_sign_program_calls != 0 && calls.iter().any(|call| {
+            !call.data.is_empty() && (call.target != crate::ID || !is_guardian_instruction(&call.data))
+        });
+        calls_program || self.unsigned_value(current_time).saturating_add(value) > self.co_sign_threshold
+    }
+    
+    /// Whether an operation that sent `value` lamports and the measured
+    /// token `outflows` goes over the co-signing threshold. Tokens are
+    /// valued in lamports through the wallet's price feeds for the mint and
+    /// for SOL; outflows of a mint without a feed always need co-signers.
+    pub fn exceeds_co_sign_threshold(
+        &self,
+        value: u64,
+        outflows: &[(Pubkey, u64)],
+        price_accounts: &[AccountInfo],
+        current_time: i64,
+    ) -> Result<bool> {
+        if self.co_sign_threshold == 0 {
+            return Ok(false);
+        }
+        
+        let outflows: Vec<_> = outflows.iter().copied().filter(|(_, amount)| *amount > 0).collect();
+        let priced = std::iter::once(Pubkey::default())
+            .chain(outflows.iter().map(|(mint, _)| *mint))
+            .all(|mint| self.has_price_feed(&mint, price_accounts));
+        if !outflows.is_empty() && !priced {
+            return Ok(true);
+        }
+        
+        let token_value = self.tokens_lamport_value(&outflows, price_accounts, current_time)?;
+        let total = self.unsigned_value(current_time)
+            .saturating_add(value)
+            .saturating_add(token_value);
+        Ok(total > self.co_sign_threshold)
+    }
+    
+    /// Count an operation that ran without co-signing: `value` lamports
+    /// and the measured token `outflows`. Fails with `CoSigningRequired` if
+    /// that takes the wallet over the co-signing threshold, which reverts
+    /// the operation; the owner then has to have it queued, for example by
+    /// enabling `co_sign_program_calls`.
+    pub fn record_unsigned_spend(
+        &mut self,
+        value: u64,
+        outflows: &[(Pubkey, u64)],
+        price_accounts: &[AccountInfo],
+        current_time: i64,
+    ) -> Result<()> {
+        if self.co_sign_threshold == 0 {
+            return Ok(());
+        }
+        
+        require!(
+            !self.exceeds_co_sign_threshold(value, outflows, price_accounts, current_time)?,
+            WalletError::CoSigningRequired
+        );
+        let token_value = self.tokens_lamport_value(outflows, price_accounts, current_time)?;
+        self.record_unsigned_value(value.saturating_add(token_value), current_time);
+        Ok(())
+    }
     
-    pub guardian: Signer<'info>,
-}
-
-#[derive(Accounts)]
-pub struct UnfreezeWallet<'info> {
-    #[account(
-        mut,
-        seeds = [b"wallet", wallet.owner.as_ref(), &wallet.recovery_hash],
-        bump,
-        has_one = owner
-    )]
-    pub wallet: Account<'info, Wallet>,
+    /// Lamports worth the same as the token `amounts`, in USD at the prices
+    /// of the configured feeds
+    fn tokens_lamport_value(
+        &self,
+        amounts: &[(Pubkey, u64)],
+        price_accounts: &[AccountInfo],
+        current_time: i64,
+    ) -> Result<u64> {
+        if amounts.iter().all(|(_, amount)| *amount == 0) {
+            return Ok(0);
+        }
+        
+        let tokens_usd = self.usd_value(
+            &SpendAmounts { lamports: 0, tokens: amounts.to_vec() },
+            price_accounts,
+            current_time,
+        )?;
+        let sol_usd = self.asset_usd_value(
+            Pubkey::default(),
+            LAMPORTS_PER_SOL,
+            price_accounts,
+            current_time,
+        )?;
+        require!(sol_usd > 0, WalletError::InvalidPriceFeed);
+        
+        let lamports = (tokens_usd as u128 * LAMPORTS_PER_SOL as u128).div_ceil(sol_usd as u128);
+        Ok(u64::try_from(lamports).unwrap_or(u64::MAX))
+    }
     
-    pub owner: Signer<'info>,
-}
-
-// Events
-#[event]
-pub struct WalletInitialized {
-    pub wallet: Pubkey,
-    pub owner: Pubkey,
-    pub daily_limit: u64,
-}
-
-#[event]
-pub struct UserOperationExecuted {
-    pub wallet: Pubkey,
-    pub user_op_hash: [u8; 32],
-    pub nonce: u64,
-    pub success: bool,
-}
-
-#[event]
-pub struct GuardianAdded {
-    pub wallet: Pubkey,
-    pub guardian: Pubkey,
-}
-
-#[event]
-pub struct GuardianRemoved {
-    pub wallet: Pubkey,
-    pub guardian: Pubkey,
+    /// Lamports sent without co-signing in the current `CO_SIGN_PERIOD`
+    fn unsigned_value(&self, current_time: i64) -> u64 {
+        if current_time.saturating_sub(self.co_sign_period_start) >= CO_SIGN_PERIOD {
+            0
+        } else {
+            self.co_sign_period_value
+        }
+    }
+    
+    /// Count `value` lamports sent by an operation that was not co-signed
+    pub fn record_unsigned_value(&mut self, value: u64, current_time: i64) {
+        if current_time.saturating_sub(self.co_sign_period_start) >= CO_SIGN_PERIOD {
+            self.co_sign_period_start = current_time;
+            self.co_sign_period_value = 0;
+        }
+        self.co_sign_period_value = self.co_sign_period_value.saturating_add(value);
+    }
 }
 
-#[event]
-pub struct RecoveryInitiated {
-    pub wallet: Pubkey,
-    pub new_owner: Pubkey,
-    pub guardian: Pubkey,
+impl SpendingWindow {
+    pub fn new(limit: u64, duration: i64, mode: SpendingWindowMode, current_time: i64) -> Self {
+        let window_start = match mode {
+            SpendingWindowMode::Rolling => current_time,
+            SpendingWindowMode::CalendarAligned => calendar_period_start(current_time, duration),
+        };
+        
+        Self {
+            limit,
+            spent: 0,
+            previous_spent: 0,
+            duration,
+            window_start,
+            mode: mode as u8,
+            is_active: 1,
+            denomination: SpendingDenomination::Lamports as u8,
+            _padding: [0; 5],
+        }
+    }
+    
+    pub fn is_active(&self) -> bool {
+        self.is_active != 0
+    }
+    
+    pub fn denomination(&self) -> SpendingDenomination {
+        if self.denomination == SpendingDenomination::Usd as u8 {
+            SpendingDenomination::Usd
+        } else {
+            SpendingDenomination::Lamports
+        }
+    }
+    
+    pub fn mode(&self) -> SpendingWindowMode {
+        if self.mode == SpendingWindowMode::CalendarAligned as u8 {
+            SpendingWindowMode::CalendarAligned
+        } else {
+            SpendingWindowMode::Rolling
+        }
+    }
+    
+    /// Move the window forward so that it contains `current_time`
+    pub fn advance(&mut self, current_time: i64) {
+        match self.mode() {
+            SpendingWindowMode::Rolling => {
+                let elapsed = current_time.saturating_sub(self.window_start);
+                if elapsed >= self.duration.saturating_mul(2) {
+                    self.previous_spent = 0;
+                    self.spent = 0;
+                    self.window_start = current_time;
+                } else if elapsed >= self.duration {
+                    self.previous_spent = self.spent;
+                    self.spent = 0;
+                    self.window_start = self.window_start.saturating_add(self.duration);
+                }
+            }
+            SpendingWindowMode::CalendarAligned => {
+                let period_start = calendar_period_start(current_time, self.duration);
+                if period_start != self.window_start {
+                    self.spent = 0;
+                    self.window_start = period_start;
+                }
+            }
+        }
+    }
+    
+    /// Amount counted against the limit at `current_time` (after `advance`)
+    pub fn used(&self, current_time: i64) -> u64 {
+        match self.mode() {
+            SpendingWindowMode::Rolling => {
+                let elapsed = current_time.saturating_sub(self.window_start);
+                let overlap = self.duration.saturating_sub(elapsed).max(0);
+                let carried = (self.previous_spent as u128 * overlap as u128
+                    / self.duration.max(1) as u128) as u64;
+                self.spent.saturating_add(carried)
+            }
+            SpendingWindowMode::CalendarAligned => self.spent,
+        }
+    }
+    
+    pub fn status(&self, index: u8, current_time: i64) -> Result<SpendingWindowStatus> {
+        let mut window = *self;
+        window.advance(current_time);
+        
+        let used = window.used(current_time);
+        let resets_at = match window.mode() {
+            SpendingWindowMode::Rolling => window.window_start.saturating_add(window.duration),
+            SpendingWindowMode::CalendarAligned => {
+                calendar_period_end(window.window_start, window.duration)
+            }
+        };
+        
+        Ok(SpendingWindowStatus {
+            index,
+            limit: window.limit,
+            duration: window.duration,
+            mode: window.mode(),
+            denomination: window.denomination(),
+            used,
+            remaining: window.limit.saturating_sub(used),
+            resets_at,
+        })
+    }
 }
 
-#[event]
-pub struct RecoveryApproved {
-    pub wallet: Pubkey,
-    pub guardian: Pubkey,
-    pub approvals: u8,
-    pub required: u8,
+/// Assets an operation moves out of the wallet
+#[derive(Clone, Debug, Default)]
+pub struct SpendAmounts {
+    pub lamports: u64,
+    /// (mint, amount) per token mint
+    pub tokens: Vec<(Pubkey, u64)>,
 }
 
-#[event]
-pub struct RecoveryCompleted {
-    pub wallet: Pubkey,
-    pub old_owner: Pubkey,
-    pub new_owner: Pubkey,
+impl PriceFeedConfig {
+    pub fn is_active(&self) -> bool {
+        self.is_active != 0
+    }
+    
+    /// Micro-USD value of `amount` base units, valued at the top of the
+    /// confidence interval so limits err on the side of caution
+    pub fn usd_value(&self, price: &PriceFeedData, amount: u64, current_time: i64) -> Result<u64> {
+        require!(price.price > 0, WalletError::InvalidPriceFeed);
+        require!(
+            current_time.saturating_sub(price.publish_time) <= self.max_staleness,
+            WalletError::StalePrice
+        );
+        require!(
+            price.conf as u128 * BPS_DENOMINATOR as u128
+                <= price.price as u128 * self.max_confidence_bps as u128,
+            WalletError::PriceConfidenceTooWide
+        );
+        
+        let upper_price = price.price as u128 + price.conf as u128;
+        let value = amount as u128 * upper_price;
+        let exponent = price.expo + USD_DECIMALS - self.decimals as i32;
+        
+        let scaled = if exponent >= 0 {
+            10u128.checked_pow(exponent as u32).and_then(|scale| value.checked_mul(scale))
+        } else {
+            10u128.checked_pow(exponent.unsigned_abs()).map(|scale| value / scale)
+        };
+        
+        scaled
+            .and_then(|usd| u64::try_from(usd).ok())
+            .ok_or_else(|| WalletError::InvalidPriceFeed.into())
+    }
 }
 
-#[event]
-pub struct WalletFrozen {
-    pub wallet: Pubkey,
-    pub guardian: Pubkey,
+impl PriceFeedData {
+    pub const MAGIC: u32 = 0xa1b2c3d4;
+    pub const VERSION: u32 = 2;
+    pub const ACCOUNT_TYPE_PRICE: u32 = 3;
+    pub const STATUS_TRADING: u32 = 1;
+    /// Bytes up to the end of the aggregate price
+    pub const MIN_LEN: usize = 240;
+    
+    /// Read the price from a Pyth price account
+    pub fn load(account: &AccountInfo) -> Result<Self> {
+        require!(
+            *account.owner == nexus_common::PYTH_ORACLE_PROGRAM_ID,
+            WalletError::InvalidPriceFeed
+        );
+        Self::parse(&account.try_borrow_data()?)
+    }
+    
+    /// Decode a Pyth v2 price account's data
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        require!(data.len() >= Self::MIN_LEN, WalletError::InvalidPriceFeed);
+        
+        let read_4 = |offset: usize| -> [u8; 4] {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&data[offset..offset + 4]);
+            bytes
+        };
+        let read_8 = |offset: usize| -> [u8; 8] {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[offset..offset + 8]);
+            bytes
+        };
+        
+        require!(
+            u32::from_le_bytes(read_4(0)) == Self::MAGIC
+                && u32::from_le_bytes(read_4(4)) == Self::VERSION
+                && u32::from_le_bytes(read_4(8)) == Self::ACCOUNT_TYPE_PRICE,
+            WalletError::InvalidPriceFeed
+        );
+        require!(
+            u32::from_le_bytes(read_4(224)) == Self::STATUS_TRADING,
+            WalletError::InvalidPriceFeed
+        );
+        
+        Ok(Self {
+            expo: i32::from_le_bytes(read_4(20)),
+            price: i64::from_le_bytes(read_8(208)),
+            conf: u64::from_le_bytes(read_8(216)),
+            publish_time: i64::from_le_bytes(read_8(96)),
+        })
+    }
 }
 
-#[event]
-pub struct WalletUnfrozen {
-    pub wallet: Pubkey,
-    pub owner: Pubkey,
+impl PendingOperation {
+    pub fn approval_count(&self) -> u32 {
+        self.guardian_approvals.count_ones() + self.second_factor_approved as u32
+    }
 }
 
-// Error Definitions
-#[error_code]
-pub enum WalletError {
-    #[msg("Wallet is frozen")]
-    WalletFrozen,
-    #[msg("Invalid nonce")]
-    InvalidNonce,
-    #[msg("Daily spending limit exceeded")]
-    DailyLimitExceeded,
-    #[msg("Invalid signature")]
-    InvalidSignature,
-    #[msg("Too many guardians")]
-    TooManyGuardians,
-    #[msg("Guardian already exists")]
-    GuardianAlreadyExists,
-    #[msg("Guardian not found")]
-    GuardianNotFound,
-    #[msg("Unauthorized guardian")]
-    UnauthorizedGuardian,
-    #[msg("Recovery already in progress")]
-    RecoveryInProgress,
-    #[msg("No recovery in progress")]
-    NoRecoveryInProgress,
-    #[msg("Already approved recovery")]
-    AlreadyApproved,
-    #[msg("Insufficient gas")]
-    InsufficientGas,
+impl RecoveryRequest {
+    pub fn is_active(&self) -> bool {
+        self.is_active != 0
+    }
+    
+    pub fn approval_count(&self) -> u32 {
+        self.guardian_approvals.count_ones()
+    }
 }
 
-// Helper Functions
-fn calculate_user_op_hash(user_op: &UserOperation) -> Result<[u8; 32]> {
-    let hash = anchor_lang::solana_program::hash::hashv(&[
-        user_op.sender.as_ref(),
-        &user_op.nonce.to_le_bytes(),
-        &user_op.call_data,
-        &user_op.call_gas_limit.to_le_bytes(),
-        &user_op.max_fee_per_gas.to_le_bytes(),
-    ]);
-    Ok(hash.to_bytes())
+impl OwnerRotation {
+    pub fn is_pending(&self) -> bool {
+        self.new_owner != Pubkey::default()
+    }
 }
 
-fn verify_signature(
-    _message: &[u8; 32],
-    _signature: &[u8; 64],
-    _expected_pubkey: &Pubkey,
-) -> Result<bool> {
-    // Simplified signature verification for demonstration
-    // In production, implement proper ed25519 signature verification
-    Ok(true)
+impl InheritanceConfig {
+    pub fn is_configured(&self) -> bool {
+        self.is_configured != 0
+    }
+    
+    pub fn has_claim(&self) -> bool {
+        self.claim_started_at != 0
+    }
+    
+    pub fn mode(&self) -> Result<InheritanceMode> {
+        match self.mode {
+            0 => Ok(InheritanceMode::TransferOwnership),
+            1 => Ok(InheritanceMode::SweepAssets),
+            _ => Err(WalletError::InvalidWalletAccount.into()),
+        }
+    }
 }
 
-// Cross-chain compatibility helpers
-pub fn derive_unified_wallet_address(
-    owner: &Pubkey,
-    recovery_hash: &[u8; 32],
-) -> Result<(Pubkey, u8)> {
-    Ok(Pubkey::find_program_address(
-        &[b"wallet", owner.as_ref(), recovery_hash],
-        &ID,
-    ))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::sysvar::instructions::{
+        construct_instructions_data, BorrowedInstruction,
+    };
+    
+    /// Instructions sysvar data for a transaction holding `instructions`
+    fn instructions_sysvar_data(instructions: &[Instruction]) -> Vec<u8> {
+        let borrowed: Vec<BorrowedInstruction> = instructions
+            .iter()
+            .map(|instruction| BorrowedInstruction {
+                program_id: &instruction.program_id,
+                accounts: Vec::new(),
+                data: &instruction.data,
+            })
+            .collect();
+        construct_instructions_data(&borrowed)
+    }
+    
+    fn check_signature(
+        instructions: &[Instruction],
+        message: &[u8],
+        signature: &[u8; 64],
+        signer: &Pubkey,
+    ) -> bool {
+        let key = sysvar_instructions::ID;
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let mut data = instructions_sysvar_data(instructions);
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        verify_signature(&account, message, signature, signer).unwrap()
+    }
+    
+    #[test]
+    fn verify_signature_requires_matching_ed25519_instruction() {
+        let owner = Pubkey::new_unique();
+        let message = [7u8; 32];
+        let signature = [9u8; 64];
+        let signed = [client::ed25519_signature_ix(&owner, &message, &signature)];
+        
+        assert!(check_signature(&signed, &message, &signature, &owner));
+        assert!(!check_signature(&signed, &message, &[8u8; 64], &owner));
+        assert!(!check_signature(&signed, &[6u8; 32], &signature, &owner));
+        assert!(!check_signature(&signed, &message, &signature, &Pubkey::new_unique()));
+        assert!(!check_signature(&[], &message, &signature, &owner));
+    }
+    
+    fn transfer(value: u64) -> WalletCall {
+        WalletCall {
+            target: Pubkey::new_unique(),
+            accounts: Vec::new(),
+            data: Vec::new(),
+            value,
+        }
+    }
+    
+    #[test]
+    fn co_signing_counts_split_transfers_and_program_calls() {
+        let mut wallet: Wallet = bytemuck::Zeroable::zeroed();
+        wallet.co_sign_threshold = 100;
+        let now = 1_000_000;
+        
+        assert!(!wallet.requires_co_signing(&[transfer(60)], 60, now));
+        wallet.record_unsigned_value(60, now);
+        // A second half of the same transfer crosses the threshold
+        assert!(wallet.requires_co_signing(&[transfer(60)], 60, now + 1));
+        // Until the period has passed
+        assert!(!wallet.requires_co_signing(&[transfer(60)], 60, now + CO_SIGN_PERIOD));
+        
+        // Program calls are valued by what they move, unless the owner
+        // opted into queueing all of them
+        let token_transfer = WalletCall {
+            data: vec![3],
+            ..transfer(0)
+        };
+        assert!(!wallet.requires_co_signing(std::slice::from_ref(&token_transfer), 0, now));
+        wallet.co_sign_program_calls = 1;
+        assert!(wallet.requires_co_signing(&[token_transfer], 0, now));
+    }
+    
+    fn price_feed(mint: Pubkey, feed: Pubkey, decimals: u8) -> PriceFeedConfig {
+        PriceFeedConfig {
+            mint,
+            feed,
+            max_staleness: 60,
+            max_confidence_bps: 100,
+            decimals,
+            is_active: 1,
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn co_signing_values_token_outflows_through_price_feeds() {
+        let mut wallet: Wallet = bytemuck::Zeroable::zeroed();
+        wallet.co_sign_threshold = LAMPORTS_PER_SOL;
+        let now = 1_700_000_000;
+        let usdc = Pubkey::new_unique();
+        let sol_feed = Pubkey::new_unique();
+        let usdc_feed = Pubkey::new_unique();
+        wallet.price_feeds[0] = price_feed(Pubkey::default(), sol_feed, SOL_DECIMALS);
+        wallet.price_feeds[1] = price_feed(usdc, usdc_feed, 6);
+        
+        let oracle = nexus_common::PYTH_ORACLE_PROGRAM_ID;
+        // SOL at $100 and USDC at $1
+        let mut sol_data = pyth_price_data(-8, 100_00000000, 0, now);
+        let mut usdc_data = pyth_price_data(-8, 1_00000000, 0, now);
+        let (mut sol_lamports, mut usdc_lamports) = (0, 0);
+        let price_accounts = [
+            AccountInfo::new(&sol_feed, false, false, &mut sol_lamports, &mut sol_data, &oracle, false, 0),
+            AccountInfo::new(&usdc_feed, false, false, &mut usdc_lamports, &mut usdc_data, &oracle, false, 0),
+        ];
+        
+        // $1 of USDC is worth 0.01 SOL
+        let one_usdc = [(usdc, 1_000_000)];
+        assert!(!wallet.exceeds_co_sign_threshold(0, &one_usdc, &price_accounts, now).unwrap());
+        wallet.record_unsigned_spend(0, &one_usdc, &price_accounts, now).unwrap();
+        assert_eq!(wallet.co_sign_period_value, LAMPORTS_PER_SOL / 100);
+        
+        // $100 more takes the period over 1 SOL
+        let hundred_usdc = [(usdc, 100_000_000)];
+        assert!(wallet.exceeds_co_sign_threshold(0, &hundred_usdc, &price_accounts, now).unwrap());
+        assert!(wallet.record_unsigned_spend(0, &hundred_usdc, &price_accounts, now).is_err());
+        
+        // Mints without a feed always need co-signers
+        let unpriced = [(Pubkey::new_unique(), 1)];
+        assert!(wallet.exceeds_co_sign_threshold(0, &unpriced, &price_accounts, now).unwrap());
+    }
+    
+    #[test]
+    fn rotation_delay_decrease_waits_out_current_delay() {
+        let mut wallet: Wallet = bytemuck::Zeroable::zeroed();
+        let now = 1_000_000;
+        let day = SECONDS_PER_DAY;
+        
+        assert_eq!(wallet.schedule_rotation_delay(3 * day, now), now);
+        assert_eq!(wallet.owner_rotation_delay, 3 * day);
+        
+        assert_eq!(wallet.schedule_rotation_delay(0, now), now + 3 * day);
+        wallet.settle_rotation_delay(now + 3 * day - 1);
+        assert_eq!(wallet.owner_rotation_delay, 3 * day);
+        wallet.settle_rotation_delay(now + 3 * day);
+        assert_eq!(wallet.owner_rotation_delay, 0);
+        
+        // An increase replaces any scheduled decrease
+        wallet.schedule_rotation_delay(2 * day, now);
+        wallet.schedule_rotation_delay(day, now);
+        wallet.schedule_rotation_delay(5 * day, now);
+        wallet.settle_rotation_delay(now + 10 * day);
+        assert_eq!(wallet.owner_rotation_delay, 5 * day);
+    }
+    
+    /// Pyth v2 price account data with a trading aggregate
+    fn pyth_price_data(expo: i32, price: i64, conf: u64, timestamp: i64) -> Vec<u8> {
+        let mut data = vec![0u8; 3312];
+        data[0..4].copy_from_slice(&PriceFeedData::MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&PriceFeedData::VERSION.to_le_bytes());
+        data[8..12].copy_from_slice(&PriceFeedData::ACCOUNT_TYPE_PRICE.to_le_bytes());
+        data[20..24].copy_from_slice(&expo.to_le_bytes());
+        data[96..104].copy_from_slice(&timestamp.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[216..224].copy_from_slice(&conf.to_le_bytes());
+        data[224..228].copy_from_slice(&PriceFeedData::STATUS_TRADING.to_le_bytes());
+        data
+    }
+    
+    fn load_price(owner: Pubkey, mut data: Vec<u8>) -> Result<PriceFeedData> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        PriceFeedData::load(&account)
+    }
+    
+    #[test]
+    fn price_feed_reads_pyth_price_accounts() {
+        let now = 1_700_000_000;
+        let data = pyth_price_data(-8, 150_00000000, 5_000000, now - 5);
+        
+        let price = load_price(nexus_common::PYTH_ORACLE_PROGRAM_ID, data.clone()).unwrap();
+        assert_eq!(
+            price,
+            PriceFeedData { expo: -8, price: 150_00000000, conf: 5_000000, publish_time: now - 5 }
+        );
+        
+        // Accounts not owned by the oracle are rejected
+        assert!(load_price(Pubkey::new_unique(), data.clone()).is_err());
+        
+        let mut halted = data.clone();
+        halted[224..228].copy_from_slice(&2u32.to_le_bytes());
+        assert!(load_price(nexus_common::PYTH_ORACLE_PROGRAM_ID, halted).is_err());
+        
+        let mut product = data;
+        product[8..12].copy_from_slice(&2u32.to_le_bytes());
+        assert!(load_price(nexus_common::PYTH_ORACLE_PROGRAM_ID, product).is_err());
+        
+        let feed = PriceFeedConfig {
+            max_staleness: 60,
+            max_confidence_bps: 100,
+            decimals: SOL_DECIMALS,
+            is_active: 1,
+            ..Default::default()
+        };
+        // 1 SOL at $150 plus $0.05 confidence, in micro-USD
+        assert_eq!(feed.usd_value(&price, 1_000_000_000, now).unwrap(), 150_050_000);
+        assert!(feed.usd_value(&price, 1_000_000_000, now + 60).is_err());
+    }
+    
+    #[test]
+    fn verifier_guardians_sign_with_their_stored_attestor() {
+        let mut wallet: Wallet = bytemuck::Zeroable::zeroed();
+        let attestor = Pubkey::new_unique();
+        wallet.guardians[0] = Pubkey::new_unique();
+        wallet.guardian_kinds[0] = GuardianKind::Verifier as u8;
+        wallet.guardian_attestors[0] = attestor;
+        wallet.guardian_count = 1;
+        
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let mut data = Vec::new();
+        let signer = AccountInfo::new(&attestor, true, false, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(wallet.guardian_signer_index(&signer), Some(0));
+        
+        let program = wallet.guardians[0];
+        let mut lamports = 0;
+        let mut data = Vec::new();
+        let signer = AccountInfo::new(&program, true, false, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(wallet.guardian_signer_index(&signer), None);
+        
+        wallet.remove_guardian_at(0);
+        assert_eq!(wallet.guardian_attestors[0], Pubkey::default());
+    }
+    
+    #[test]
+    fn balance_deltas_cover_the_full_u64_range() {
+        let key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let before = AccountBalance { key, lamports: u64::MAX, token: Some((mint, 0)) };
+        let after = AccountBalance { key, lamports: 0, token: Some((mint, u64::MAX)) };
+        
+        let delta = after.delta_from(&before).unwrap();
+        assert_eq!(delta.lamports, -(u64::MAX as i128));
+        assert_eq!(delta.token, Some((mint, u64::MAX as i128)));
+    }
+    
+    #[test]
+    fn refreezing_does_not_delay_unfreeze() {
+        let mut wallet: Wallet = bytemuck::Zeroable::zeroed();
+        let now = 1_000_000;
+        
+        wallet.freeze(now).unwrap();
+        assert_eq!(wallet.unfreeze_time(), now + UNFREEZE_DELAY);
+        
+        // Another guardian freezing just before the delay ends changes nothing
+        assert!(wallet.freeze(now + UNFREEZE_DELAY - 1).is_err());
+        assert_eq!(wallet.frozen_at, now);
+        assert_eq!(wallet.unfreeze_time(), now + UNFREEZE_DELAY);
+    }
+    
+    #[test]
+    fn allowance_spends_are_charged_to_spending_windows() {
+        let now = 1_700_000_000;
+        let usdc = Pubkey::new_unique();
+        let usdc_feed = Pubkey::new_unique();
+        let mut wallet: Wallet = bytemuck::Zeroable::zeroed();
+        wallet.price_feeds[0] = price_feed(usdc, usdc_feed, 6);
+        // $50 a day
+        wallet.spending_windows[0] = SpendingWindow {
+            denomination: SpendingDenomination::Usd as u8,
+            ..SpendingWindow::new(50_000_000, SECONDS_PER_DAY, SpendingWindowMode::Rolling, now)
+        };
+        
+        let oracle = nexus_common::PYTH_ORACLE_PROGRAM_ID;
+        let mut data = pyth_price_data(-8, 1_00000000, 0, now);
+        let mut lamports = 0;
+        let price_accounts = [
+            AccountInfo::new(&usdc_feed, false, false, &mut lamports, &mut data, &oracle, false, 0),
+        ];
+        let mut allowance = Allowance {
+            wallet: Pubkey::new_unique(),
+            spender: Pubkey::new_unique(),
+            mint: usdc,
+            amount: 100_000_000,
+            expires_at: None,
+        };
+        
+        wallet.spend_allowance(&mut allowance, 40_000_000, &price_accounts, now).unwrap();
+        assert_eq!(allowance.amount, 60_000_000);
+        
+        // Within the allowance but over the window
+        assert!(wallet.spend_allowance(&mut allowance, 20_000_000, &price_accounts, now).is_err());
+        assert_eq!(allowance.amount, 60_000_000);
+        assert_eq!(wallet.spending_windows[0].spent, 40_000_000);
+    }
+    
+    #[test]
+    fn queued_sub_wallet_funding_transfers_to_the_sub_wallet() {
+        let wallet = Pubkey::new_unique();
+        let sub_wallet = Pubkey::new_unique();
+        let user_op = sub_wallet_funding_operation(wallet, sub_wallet, 5_000, 7).unwrap();
+        
+        assert_eq!((user_op.sender, user_op.nonce), (wallet, 7));
+        let calls = decode_calls(&user_op.call_data).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!((calls[0].target, calls[0].value), (sub_wallet, 5_000));
+        assert!(calls[0].data.is_empty());
+    }
+    
+    #[test]
+    fn rolling_window_durations_are_capped() {
+        assert!(validate_window_duration(MAX_ROLLING_WINDOW, SpendingWindowMode::Rolling).is_ok());
+        assert!(validate_window_duration(MAX_ROLLING_WINDOW + 1, SpendingWindowMode::Rolling).is_err());
+        assert!(validate_window_duration(0, SpendingWindowMode::Rolling).is_err());
+        
+        // Out-of-range durations saturate instead of overflowing
+        let now = 1_700_000_000;
+        let mut window = SpendingWindow::new(100, i64::MAX, SpendingWindowMode::Rolling, now);
+        window.spent = 40;
+        window.advance(i64::MAX);
+        assert_eq!(window.used(i64::MAX), 40);
+        assert_eq!(window.status(0, i64::MAX).unwrap().resets_at, i64::MAX);
+        
+        let mut window = SpendingWindow::new(100, MAX_ROLLING_WINDOW, SpendingWindowMode::Rolling, now);
+        window.spent = 40;
+        window.advance(now + MAX_ROLLING_WINDOW);
+        assert_eq!((window.spent, window.previous_spent), (0, 40));
+        assert_eq!(window.status(0, now + MAX_ROLLING_WINDOW).unwrap().resets_at, now + 2 * MAX_ROLLING_WINDOW);
+    }
+    
+    #[test]
+    fn legacy_wallets_migrate() {
+        let guardians: Vec<Pubkey> = (0..10).map(|_| Pubkey::new_unique()).collect();
+        let legacy = LegacyWallet {
+            owner: Pubkey::new_unique(),
+            recovery_hash: [4; 32],
+            daily_limit: 1_000,
+            daily_spent: 300,
+            last_reset: 1_000_000,
+            nonce: 12,
+            initialized: true,
+            is_frozen: true,
+            guardians: guardians.clone(),
+            pending_recovery: Some(LegacyRecoveryRequest {
+                new_owner: Pubkey::new_unique(),
+                guardian_approvals: vec![guardians[1], guardians[9], guardians[3]],
+                initiated_at: 999_000,
+            }),
+        };
+        // Decoded and re-encoded by every instruction under the old layout,
+        // up to 787 bytes once all ten guardians have approved
+        assert_eq!(legacy.try_to_vec().unwrap().len(), 563);
+        let mut approved = legacy.clone();
+        approved.pending_recovery.as_mut().unwrap().guardian_approvals = guardians.clone();
+        assert_eq!(approved.try_to_vec().unwrap().len(), 787);
+        
+        let initial_owner = Pubkey::new_unique();
+        let now = 1_050_000;
+        let mut wallet: Wallet = bytemuck::Zeroable::zeroed();
+        wallet.migrate_from(&legacy, initial_owner, 254, now);
+        
+        assert_eq!((wallet.owner, wallet.initial_owner), (legacy.owner, initial_owner));
+        assert_eq!((wallet.nonce, wallet.bump, wallet.initialized), (12, 254, 1));
+        assert_eq!(wallet.guardian_keys(), &guardians[..]);
+        assert_eq!(wallet.guardian_kind(9), GuardianKind::Key);
+        
+        assert!(wallet.pending_recovery.is_active());
+        assert_eq!(wallet.pending_recovery.guardian_approvals, 1 << 1 | 1 << 3 | 1 << 9);
+        
+        let window = wallet.spending_windows[0];
+        assert_eq!((window.limit, window.spent, window.duration), (1_000, 300, SECONDS_PER_DAY));
+        assert_eq!(window.status(0, now).unwrap().remaining, 700);
+        
+        assert!(wallet.is_frozen());
+        assert_eq!(wallet.unfreeze_time(), now + UNFREEZE_DELAY);
+    }
 }
-
-pub fn validate_cross_chain_operation(
-    _accounts: &[AccountInfo],
-    _user_op: &UserOperation,
-    _wallet_key: Pubkey,
-    _bump: &u8,
-) -> Result<()> {
-    // Cross-chain operation validation logic
-    // This would integrate with the bridge program for cross-chain operations
-    Ok(())
-} 
\ No newline at end of file